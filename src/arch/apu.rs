@@ -0,0 +1,180 @@
+use std::cell::{Cell, RefCell};
+
+use crate::arch::snapshot::StateIo;
+
+/// CPUクロック(NTSC)
+pub(crate) const CPU_CLOCK: u32 = 1_789_773;
+/// 出力サンプリングレート
+pub const SAMPLE_RATE: u32 = 44_100;
+/// UIへ渡す単位(約1フレーム)
+const CHUNK: usize = 735;
+
+/// 長さカウンタの初期値
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14, 12, 16, 24, 18, 48, 20, 96, 22,
+    192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+/// デューティ比ごとの波形
+const DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+/// 音声出力
+/// 2A03内蔵音源は未実装, カートリッジの拡張音源のみミックスする
+#[derive(Default)]
+pub(crate) struct Mixer {
+    cycle: Cell<u32>,
+    samples: RefCell<Vec<i16>>,
+}
+
+impl Mixer {
+    /// level: -1.0-1.0
+    pub(crate) fn clock(&self, cycle: u32, level: f32) {
+        let mut total = self.cycle.get() + cycle * SAMPLE_RATE;
+        while total >= CPU_CLOCK {
+            total -= CPU_CLOCK;
+            let sample = level.clamp(-1.0, 1.0) * f32::from(i16::MAX);
+            self.samples.borrow_mut().push(sample as i16);
+        }
+        self.cycle.set(total);
+    }
+
+    /// CHUNK分溜まったら取り出す
+    pub(crate) fn drain(&self) -> Option<Vec<i16>> {
+        let mut samples = self.samples.borrow_mut();
+        if samples.len() < CHUNK {
+            return None;
+        }
+        Some(samples.drain(..).collect())
+    }
+}
+
+/// 2A03互換の矩形波 (スイープなし)
+/// - 0 DDLC VVVV: duty, 長さ停止/エンベロープループ, 固定音量, 音量/エンベロープ周期
+/// - 2 タイマ下位
+/// - 3 LLLL LHHH: 長さ, タイマ上位
+#[derive(Default)]
+pub(crate) struct Pulse {
+    duty: Cell<u8>,
+    halt: Cell<bool>,
+    constant: Cell<bool>,
+    volume: Cell<u8>,
+    period: Cell<u16>,
+    timer: Cell<u16>,
+    step: Cell<u8>,
+    length: Cell<u8>,
+    enabled: Cell<bool>,
+    envelope_start: Cell<bool>,
+    envelope_divider: Cell<u8>,
+    envelope_decay: Cell<u8>,
+}
+
+impl Pulse {
+    pub(crate) fn write(&self, reg: usize, value: u8) {
+        match reg {
+            0 => {
+                self.duty.set(value >> 6);
+                self.halt.set(value & 0x20 != 0);
+                self.constant.set(value & 0x10 != 0);
+                self.volume.set(value & 0x0F);
+            }
+            2 => self
+                .period
+                .set((self.period.get() & 0x0700) | u16::from(value)),
+            3 => {
+                self.period
+                    .set((self.period.get() & 0x00FF) | u16::from(value & 0x07) << 8);
+                if self.enabled.get() {
+                    self.length.set(LENGTH_TABLE[usize::from(value >> 3)]);
+                }
+                self.step.set(0);
+                self.envelope_start.set(true);
+            }
+            _ => (),
+        }
+    }
+
+    pub(crate) fn set_enabled(&self, enabled: bool) {
+        self.enabled.set(enabled);
+        if !enabled {
+            self.length.set(0);
+        }
+    }
+
+    pub(crate) fn is_active(&self) -> bool {
+        self.length.get() > 0
+    }
+
+    /// APUクロック(CPU 2サイクル)ごと
+    pub(crate) fn clock_timer(&self) {
+        if self.timer.get() == 0 {
+            self.timer.set(self.period.get());
+            self.step.set((self.step.get() + 1) & 0x07);
+        } else {
+            self.timer.set(self.timer.get() - 1);
+        }
+    }
+
+    pub(crate) fn clock_envelope(&self) {
+        if self.envelope_start.get() {
+            self.envelope_start.set(false);
+            self.envelope_decay.set(15);
+            self.envelope_divider.set(self.volume.get());
+        } else if self.envelope_divider.get() == 0 {
+            self.envelope_divider.set(self.volume.get());
+            match self.envelope_decay.get() {
+                0 if self.halt.get() => self.envelope_decay.set(15),
+                0 => (),
+                decay => self.envelope_decay.set(decay - 1),
+            }
+        } else {
+            self.envelope_divider.set(self.envelope_divider.get() - 1);
+        }
+    }
+
+    pub(crate) fn clock_length(&self) {
+        if !self.halt.get() && self.length.get() > 0 {
+            self.length.set(self.length.get() - 1);
+        }
+    }
+
+    /// 0-15
+    pub(crate) fn output(&self) -> u8 {
+        let duty = DUTY_TABLE[usize::from(self.duty.get())][usize::from(self.step.get())];
+        if self.length.get() == 0 || duty == 0 {
+            0
+        } else if self.constant.get() {
+            self.volume.get()
+        } else {
+            self.envelope_decay.get()
+        }
+    }
+
+    pub(crate) fn state(&self, io: &mut StateIo) {
+        io.cell(&self.duty);
+        io.cell(&self.halt);
+        io.cell(&self.constant);
+        io.cell(&self.volume);
+        io.cell(&self.period);
+        io.cell(&self.timer);
+        io.cell(&self.step);
+        io.cell(&self.length);
+        io.cell(&self.enabled);
+        io.cell(&self.envelope_start);
+        io.cell(&self.envelope_divider);
+        io.cell(&self.envelope_decay);
+    }
+}
+
+/// 2A03の矩形波ミキサー特性
+pub(crate) fn pulse_level(pulse: u8) -> f32 {
+    if pulse == 0 {
+        0.0
+    } else {
+        95.88 / (8128.0 / f32::from(pulse) + 100.0)
+    }
+}
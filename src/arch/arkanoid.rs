@@ -0,0 +1,122 @@
+use std::cell::Cell;
+
+/// つまみの可動範囲
+const POSITION_MIN: i32 = 0x62;
+const POSITION_MAX: i32 = 0xF2;
+
+/// アルカノイド専用コントローラ(バウス)
+/// つまみの位置を8bitでシリアル出力, 上位bitから反転して読み出す
+/// - NES: $4017 bit4 つまみ, bit3 ボタン
+/// - Famicom: $4017 bit1 つまみ, $4016 bit1 ボタン
+#[derive(Debug)]
+pub struct Arkanoid {
+    position: Cell<i32>,
+    button: Cell<bool>,
+    shift: Cell<u8>,
+    strobe: Cell<bool>,
+}
+
+impl Default for Arkanoid {
+    fn default() -> Self {
+        Self {
+            position: Cell::new((POSITION_MIN + POSITION_MAX) / 2),
+            button: Cell::new(false),
+            shift: Cell::new(0x00),
+            strobe: Cell::new(false),
+        }
+    }
+}
+
+impl Arkanoid {
+    /// マウスの横移動量
+    pub fn move_by(&self, dx: i32) {
+        let position = (self.position.get() + dx).clamp(POSITION_MIN, POSITION_MAX);
+        self.position.set(position);
+    }
+
+    pub fn set_button(&self, pressed: bool) {
+        self.button.set(pressed);
+    }
+
+    pub(crate) fn write(&self, value: u8) {
+        let strobe = value & 0x01 != 0;
+        self.strobe.set(strobe);
+        if strobe {
+            self.shift.set(!(self.position.get() as u8));
+        }
+    }
+
+    pub(crate) fn button(&self) -> u8 {
+        self.button.get() as u8
+    }
+
+    /// つまみの1bit
+    pub(crate) fn serial(&self) -> u8 {
+        if self.strobe.get() {
+            return (!(self.position.get() as u8)) >> 7;
+        }
+        let shift = self.shift.get();
+        self.shift.set(shift << 1);
+        shift >> 7
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arch::input::{Expansion, Input, Port2};
+    use crate::arch::ppu::Frame;
+    use std::rc::Rc;
+
+    /// 左端 0x62 -> 反転して 0b1001_1101
+    const LEFT_BITS: [u8; 9] = [1, 0, 0, 1, 1, 1, 0, 1, 0];
+
+    fn input() -> Input {
+        let input = Input::new(Rc::new(Frame::default()));
+        input.arkanoid.move_by(-0x100);
+        input
+    }
+
+    #[test]
+    fn is_nes_serial() {
+        let input = input();
+        input.port2.set(Port2::Arkanoid);
+        input.write(1);
+        // strobe中は最上位bitのまま
+        assert_eq!(input.read(1) >> 4 & 0x01, 1);
+        assert_eq!(input.read(1) >> 4 & 0x01, 1);
+        input.write(0);
+        let bits = (0..9)
+            .map(|_| input.read(1) >> 4 & 0x01)
+            .collect::<Vec<u8>>();
+        assert_eq!(bits, LEFT_BITS);
+    }
+
+    #[test]
+    fn is_famicom_serial() {
+        let input = input();
+        input.expansion.set(Expansion::Arkanoid);
+        input.write(1);
+        input.write(0);
+        let bits = (0..9)
+            .map(|_| input.read(1) >> 1 & 0x01)
+            .collect::<Vec<u8>>();
+        assert_eq!(bits, LEFT_BITS);
+    }
+
+    #[test]
+    fn is_fire_button() {
+        let input = input();
+        input.arkanoid.move_by(0x100);
+        input.port2.set(Port2::Arkanoid);
+        assert_eq!(input.read(1) & 0x08, 0x00);
+        input.arkanoid.set_button(true);
+        assert_eq!(input.read(1) & 0x08, 0x08);
+
+        input.port2.set(Port2::Pad);
+        input.expansion.set(Expansion::Arkanoid);
+        assert_eq!(input.read(0) & 0x02, 0x02);
+        assert_eq!(input.read(1) & 0x08, 0x00);
+        input.arkanoid.set_button(false);
+        assert_eq!(input.read(0) & 0x02, 0x00);
+    }
+}
@@ -1,6 +1,6 @@
 use crate::arch::memory::CPUMemory;
 use crate::arch::op::{AddressingMode, OPCode, Operation};
-use crate::arch::register::Register;
+use crate::arch::register::{Register, State};
 use crate::arch::Opeland;
 
 pub struct CPU {
@@ -28,6 +28,24 @@ impl CPU {
         self.memory.read(addr)
     }
 
+    /// IRQ受付
+    /// Iフラグが立っていれば無視する
+    pub(crate) fn irq(&self) -> bool {
+        let state = self.register.p.get();
+        if state.i {
+            return false;
+        }
+        let pc = self.register.pc.get();
+        self.stack_push((pc >> 8) as u8);
+        self.stack_push((pc & 0xFF) as u8);
+        self.stack_push(State { b: false, ..state }.to_u8());
+        self.register.p.set(State { i: true, ..state });
+        let pc_low = u16::from(self.memory.read(0xFFFE));
+        let pc_high = u16::from(self.memory.read(0xFFFF)) << 8;
+        self.register.pc.set(pc_low + pc_high);
+        true
+    }
+
     pub(crate) fn exec(&self, opcode: &Operation) {
         let opeland = self.get_opeland(&opcode.mode);
         match (&opcode.op, opeland) {
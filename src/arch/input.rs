@@ -0,0 +1,185 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+use crate::arch::arkanoid::Arkanoid;
+use crate::arch::keyboard::Keyboard;
+use crate::arch::pad::Pad;
+use crate::arch::power_pad::PowerPad;
+use crate::arch::ppu::Frame;
+use crate::arch::recorder::DataRecorder;
+use crate::arch::zapper::Zapper;
+
+/// 多人数アダプタ
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum MultiTap {
+    /// 2P
+    #[default]
+    None,
+    /// NES Four Score
+    /// $4016: 1P, 3P, 署名 / $4017: 2P, 4P, 署名
+    FourScore,
+    /// ファミコン拡張端子
+    /// $4016 bit0: 1P, bit1: 3P / $4017 bit0: 2P, bit1: 4P
+    Famicom,
+}
+
+/// 2P側に接続する機器
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum Port2 {
+    #[default]
+    Pad,
+    Zapper,
+    /// NES版バウス
+    Arkanoid,
+    /// パワーパッド
+    PowerPad,
+}
+
+/// ファミコン拡張端子に接続する機器
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum Expansion {
+    #[default]
+    None,
+    /// ファミコン版バウス
+    Arkanoid,
+    /// ファミリーベーシック キーボード + データレコーダ
+    Keyboard,
+}
+
+/// Four Scoreの署名
+/// 17-24bit目に読み出される
+const FOUR_SCORE_SIGNATURE: [u32; 2] = [0b0000_1000, 0b0000_0100];
+
+/// $4016, $4017に接続される入力機器
+pub struct Input {
+    pub(crate) pads: [Pad; 4],
+    pub(crate) multitap: Cell<MultiTap>,
+    pub(crate) port2: Cell<Port2>,
+    pub(crate) expansion: Cell<Expansion>,
+    pub(crate) zapper: Zapper,
+    pub(crate) arkanoid: Arkanoid,
+    pub(crate) power_pad: PowerPad,
+    pub(crate) keyboard: Keyboard,
+    pub(crate) recorder: DataRecorder,
+    /// Four Score用24bitシフトレジスタ
+    four_score: [Cell<u32>; 2],
+    strobe: Cell<bool>,
+    frame: Rc<Frame>,
+}
+
+impl Input {
+    pub(crate) fn new(frame: Rc<Frame>) -> Input {
+        Input {
+            pads: Default::default(),
+            multitap: Cell::new(MultiTap::default()),
+            port2: Cell::new(Port2::default()),
+            expansion: Cell::new(Expansion::default()),
+            zapper: Zapper::new(frame.clone()),
+            arkanoid: Arkanoid::default(),
+            power_pad: PowerPad::default(),
+            keyboard: Keyboard::default(),
+            recorder: DataRecorder::default(),
+            four_score: Default::default(),
+            strobe: Cell::new(false),
+            frame,
+        }
+    }
+
+    /// $4016 Write
+    pub(crate) fn write(&self, value: u8) {
+        let frame = self.frame.count.get();
+        for pad in self.pads.iter() {
+            pad.write(value, frame);
+        }
+        self.arkanoid.write(value);
+        self.power_pad.write(value);
+        self.keyboard.write(value);
+        self.recorder.write(value);
+
+        let strobe = value & 0x01 != 0;
+        self.strobe.set(strobe);
+        if strobe {
+            self.latch_four_score();
+        }
+    }
+
+    fn latch_four_score(&self) {
+        for (port, shift) in self.four_score.iter().enumerate() {
+            let frame = self.frame.count.get();
+            let low = u32::from(self.pads[port].buttons(frame));
+            let high = u32::from(self.pads[port + 2].buttons(frame));
+            shift.set(low | high << 8 | FOUR_SCORE_SIGNATURE[port] << 16);
+        }
+    }
+
+    /// $4016 / $4017 Read
+    /// port: 0 or 1
+    pub(crate) fn read(&self, port: usize) -> u8 {
+        let value = match (port, self.port2.get()) {
+            (1, Port2::Zapper) => self.zapper.read(),
+            (1, Port2::Arkanoid) => self.arkanoid.serial() << 4 | self.arkanoid.button() << 3,
+            (1, Port2::PowerPad) => self.power_pad.read(),
+            _ => self.read_pads(port),
+        };
+        value | self.read_expansion(port)
+    }
+
+    fn read_pads(&self, port: usize) -> u8 {
+        let frame = self.frame.count.get();
+        match self.multitap.get() {
+            MultiTap::None => self.pads[port].read(frame),
+            MultiTap::FourScore => {
+                if self.strobe.get() {
+                    self.latch_four_score();
+                }
+                let shift = &self.four_score[port];
+                let bit = shift.get() & 0x01;
+                // 24bit読み切ったあとは1
+                shift.set((shift.get() >> 1) | 0x0080_0000);
+                bit as u8
+            }
+            MultiTap::Famicom => self.pads[port].read(frame) | self.pads[port + 2].read(frame) << 1,
+        }
+    }
+
+    /// 拡張端子はbit1-4
+    fn read_expansion(&self, port: usize) -> u8 {
+        match (port, self.expansion.get()) {
+            (0, Expansion::Arkanoid) => self.arkanoid.button() << 1,
+            (1, Expansion::Arkanoid) => self.arkanoid.serial() << 1,
+            (0, Expansion::Keyboard) => self.recorder.read(),
+            (1, Expansion::Keyboard) => self.keyboard.read(),
+            _ => 0x00,
+        }
+    }
+
+    pub(crate) fn clock(&self, cycle: u32) {
+        self.recorder.clock(cycle);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arch::input::{Input, MultiTap};
+    use crate::arch::pad::Button;
+    use crate::arch::ppu::Frame;
+    use std::rc::Rc;
+
+    #[test]
+    fn is_four_score_signature() {
+        let input = Input::new(Rc::new(Frame::default()));
+        input.multitap.set(MultiTap::FourScore);
+        input.pads[0].set_button(Button::A, true);
+        input.pads[3].set_button(Button::Right, true);
+        input.write(1);
+        input.write(0);
+
+        let port0 = (0..24).map(|_| input.read(0)).collect::<Vec<u8>>();
+        let port1 = (0..24).map(|_| input.read(1)).collect::<Vec<u8>>();
+        assert_eq!(port0[0], 1);
+        assert_eq!(&port0[16..], &[0, 0, 0, 1, 0, 0, 0, 0]);
+        assert_eq!(port1[15], 1);
+        assert_eq!(&port1[16..], &[0, 0, 1, 0, 0, 0, 0, 0]);
+        assert_eq!(input.read(0), 1);
+    }
+}
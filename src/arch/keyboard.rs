@@ -0,0 +1,113 @@
+use std::cell::Cell;
+
+/// キーボードの行数
+pub const ROWS: usize = 9;
+
+/// ファミリーベーシック キーボード
+/// 9行 x 2列 x 4キー
+/// $4016 Write
+/// - 2 enable
+/// - 1 column select (1 -> 0で次の行)
+/// - 0 reset (行0へ)
+///
+/// $4017 Read
+/// - 4-1 選択中の4キー (0: pressed)
+#[derive(Debug, Default)]
+pub struct Keyboard {
+    /// 行ごとの押下状態
+    /// bit0-3: 列0, bit4-7: 列1
+    keys: [Cell<u8>; ROWS],
+    row: Cell<usize>,
+    column: Cell<u8>,
+    enable: Cell<bool>,
+}
+
+impl Keyboard {
+    /// idx: 列0の0-3, 列1の4-7
+    pub fn set_key(&self, row: usize, idx: usize, pressed: bool) {
+        let keys = &self.keys[row];
+        let bit = 1u8 << idx;
+        keys.set(if pressed {
+            keys.get() | bit
+        } else {
+            keys.get() & !bit
+        });
+    }
+
+    pub fn release_all(&self) {
+        for keys in self.keys.iter() {
+            keys.set(0x00);
+        }
+    }
+
+    pub(crate) fn write(&self, value: u8) {
+        self.enable.set(value & 0x04 != 0);
+        let column = (value >> 1) & 0x01;
+        if value & 0x01 != 0 {
+            self.row.set(0);
+        } else if self.column.get() == 1 && column == 0 {
+            self.row.set(self.row.get() + 1);
+        }
+        self.column.set(column);
+    }
+
+    pub(crate) fn read(&self) -> u8 {
+        if !self.enable.get() {
+            return 0x00;
+        }
+        let keys = self
+            .keys
+            .get(self.row.get())
+            .map(|keys| keys.get() >> (4 * self.column.get()))
+            .unwrap_or(0x00);
+        (!keys & 0x0F) << 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arch::keyboard::{Keyboard, ROWS};
+
+    /// 行0から全行の(列0, 列1)を読む
+    fn scan(keyboard: &Keyboard) -> Vec<(u8, u8)> {
+        keyboard.write(0x05);
+        (0..ROWS)
+            .map(|_| {
+                keyboard.write(0x04);
+                let low = keyboard.read();
+                keyboard.write(0x06);
+                let high = keyboard.read();
+                (low, high)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn is_matrix_scan() {
+        let keyboard = Keyboard::default();
+        keyboard.set_key(0, 2, true);
+        keyboard.set_key(3, 5, true);
+        keyboard.set_key(8, 7, true);
+        let rows = scan(&keyboard);
+        assert_eq!(rows[0], (0x16, 0x1E));
+        assert_eq!(rows[1], (0x1E, 0x1E));
+        assert_eq!(rows[3], (0x1E, 0x1A));
+        assert_eq!(rows[8], (0x1E, 0x0E));
+
+        keyboard.set_key(3, 5, false);
+        assert_eq!(scan(&keyboard)[3], (0x1E, 0x1E));
+    }
+
+    #[test]
+    fn is_disabled_and_out_of_rows() {
+        let keyboard = Keyboard::default();
+        keyboard.set_key(0, 0, true);
+        keyboard.write(0x01);
+        assert_eq!(keyboard.read(), 0x00);
+
+        scan(&keyboard);
+        // 10行目以降は押下なし
+        keyboard.write(0x04);
+        assert_eq!(keyboard.read(), 0x1E);
+    }
+}
@@ -0,0 +1,100 @@
+use std::cell::Cell;
+
+use crate::arch::mapper::{open_bus, BankMemory, Mapper};
+use crate::arch::ppu::Mirroring;
+use crate::arch::snapshot::StateIo;
+use crate::parser::INes;
+
+/// Mapper 7
+/// - $8000-$FFFF Write
+///   - 4 1画面ミラーリングの選択
+///   - 2-0 PRG 32KB 切り替え
+/// - CHR-RAM 8KB
+pub(crate) struct AxROM {
+    prg: BankMemory,
+    chr: BankMemory,
+    prg_ram: BankMemory,
+    bank: Cell<u8>,
+}
+
+impl AxROM {
+    pub(crate) fn new(rom: INes) -> AxROM {
+        let prg_ram = BankMemory::prg_ram(&rom);
+        let chr = BankMemory::chr(rom.chr);
+        AxROM {
+            prg: BankMemory::rom(rom.prg),
+            chr,
+            prg_ram,
+            bank: Cell::new(0x00),
+        }
+    }
+}
+
+impl Mapper for AxROM {
+    fn cpu_read(&self, addr: usize) -> u8 {
+        match addr {
+            0x4020..=0x5FFF => open_bus(addr),
+            0x6000..=0x7FFF => self.prg_ram.read(0x2000, 0, addr - 0x6000),
+            _ => self
+                .prg
+                .read(0x8000, usize::from(self.bank.get() & 0x07), addr - 0x8000),
+        }
+    }
+
+    fn cpu_write(&self, addr: usize, value: u8) {
+        match addr {
+            0x6000..=0x7FFF => self.prg_ram.write(0x2000, 0, addr - 0x6000, value),
+            0x8000..=0xFFFF => self.bank.set(value),
+            _ => (),
+        }
+    }
+
+    fn ppu_read(&self, addr: usize) -> u8 {
+        self.chr.read(0x2000, 0, addr)
+    }
+
+    fn ppu_write(&self, addr: usize, value: u8) {
+        self.chr.write(0x2000, 0, addr, value)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        if self.bank.get() & 0x10 != 0 {
+            Mirroring::OneScreenUpper
+        } else {
+            Mirroring::OneScreenLower
+        }
+    }
+
+    fn prg_ram(&self) -> Option<&BankMemory> {
+        Some(&self.prg_ram)
+    }
+
+    fn chr(&self) -> Option<&BankMemory> {
+        Some(&self.chr)
+    }
+
+    fn state(&self, io: &mut StateIo) {
+        io.cell(&self.bank);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arch::mapper::axrom::AxROM;
+    use crate::arch::mapper::tests::rom;
+    use crate::arch::mapper::Mapper;
+    use crate::arch::ppu::Mirroring;
+
+    #[test]
+    fn is_bank_and_one_screen() {
+        let mapper = AxROM::new(rom(7, 0, (0x8000, 8), (0, 0)));
+        assert_eq!(mapper.mirroring(), Mirroring::OneScreenLower);
+        mapper.cpu_write(0x8000, 0x15);
+        assert_eq!(mapper.cpu_read(0x8000), 5);
+        assert_eq!(mapper.cpu_read(0xFFFF), 5);
+        assert_eq!(mapper.mirroring(), Mirroring::OneScreenUpper);
+        mapper.cpu_write(0xFFFF, 0x02);
+        assert_eq!(mapper.cpu_read(0xC000), 2);
+        assert_eq!(mapper.mirroring(), Mirroring::OneScreenLower);
+    }
+}
@@ -0,0 +1,340 @@
+use std::cell::Cell;
+
+use crate::arch::mapper::eeprom::{Eeprom, Model};
+use crate::arch::mapper::{open_bus, BankMemory, Mapper};
+use crate::arch::ppu::Mirroring;
+use crate::arch::snapshot::StateIo;
+use crate::parser::INes;
+
+/// Mapper 16, 153, 159
+/// レジスタはアドレス下位4bit
+/// - $0-$7 CHR 1KB (153は0bitがPRG 256KBの外側バンク)
+/// - $8 PRG 16KB ($8000), $C000-$FFFF 最終バンク固定
+/// - $9 mirroring
+/// - $A 0: IRQ有効 (書き込みでacknowledge)
+/// - $B, $C IRQカウンタ下位, 上位 (LZ93D50はlatch)
+/// - $D 7: EEPROM読み出し, 6: SDA, 5: SCL (153は5: PRG-RAM有効)
+///
+/// FCG-1/2は$6000-$7FFF, LZ93D50は$8000-$FFFFにレジスタ
+/// サブマッパー不明の16は両方で受け, $6000側はFCGとして扱う
+/// EEPROMのSDAは$6000-$7FFFの4bitで読む
+pub(crate) struct BandaiFCG {
+    prg: BankMemory,
+    chr: BankMemory,
+    /// 153のみ
+    prg_ram: BankMemory,
+    eeprom: Option<Eeprom>,
+    /// $6000-$7FFF, $8000-$FFFFのレジスタ
+    registers: (bool, bool),
+    outer_bank: bool,
+    chr_banks: [Cell<u8>; 8],
+    prg_bank: Cell<u8>,
+    mirroring: Cell<u8>,
+    control: Cell<u8>,
+    irq_enabled: Cell<bool>,
+    irq_latch: Cell<u16>,
+    irq_counter: Cell<u16>,
+    irq_pending: Cell<bool>,
+}
+
+impl BandaiFCG {
+    pub(crate) fn new(rom: INes) -> BandaiFCG {
+        let (eeprom, registers) = match (rom.mapper(), rom.submapper()) {
+            (153, _) => (None, (false, true)),
+            (159, _) => (Some(Eeprom::new(Model::X24C01)), (false, true)),
+            (_, 4) => (None, (true, false)),
+            (_, 5) => (Some(Eeprom::new(Model::C24C02)), (false, true)),
+            _ => (Some(Eeprom::new(Model::C24C02)), (true, true)),
+        };
+        let outer_bank = rom.mapper() == 153;
+        let prg_ram = if outer_bank {
+            BankMemory::prg_ram(&rom)
+        } else {
+            BankMemory::ram(0)
+        };
+        let chr = BankMemory::chr(rom.chr);
+        BandaiFCG {
+            prg: BankMemory::rom(rom.prg),
+            chr,
+            prg_ram,
+            eeprom,
+            registers,
+            outer_bank,
+            chr_banks: Default::default(),
+            prg_bank: Cell::new(0x00),
+            mirroring: Cell::new(0x00),
+            control: Cell::new(0x00),
+            irq_enabled: Cell::new(false),
+            irq_latch: Cell::new(0x0000),
+            irq_counter: Cell::new(0x0000),
+            irq_pending: Cell::new(false),
+        }
+    }
+
+    /// 16KB単位
+    fn prg_bank(&self, addr: usize) -> usize {
+        let outer = if self.outer_bank {
+            usize::from(self.chr_banks.iter().any(|bank| bank.get() & 0x01 != 0)) << 4
+        } else {
+            0
+        };
+        let bank = match addr {
+            0x8000..=0xBFFF => usize::from(self.prg_bank.get() & 0x0F),
+            _ => 0x0F,
+        };
+        let banks = (self.prg.len() / 0x4000).max(1);
+        (outer | bank).min(banks - 1)
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        self.control.get() & 0x20 != 0
+    }
+
+    /// latch: LZ93D50の書き込み
+    fn write_register(&self, reg: usize, value: u8, latch: bool) {
+        match reg {
+            0x0..=0x7 => self.chr.switch(&self.chr_banks[reg], value),
+            0x8 => self.prg_bank.set(value),
+            0x9 => self.mirroring.set(value & 0x03),
+            0xA => {
+                self.irq_enabled.set(value & 0x01 != 0);
+                self.irq_pending.set(false);
+                if latch {
+                    self.irq_counter.set(self.irq_latch.get());
+                }
+            }
+            0xB | 0xC => {
+                let shift = (reg - 0xB) * 8;
+                let mask = !(0x00FF << shift);
+                let target = if latch {
+                    &self.irq_latch
+                } else {
+                    &self.irq_counter
+                };
+                target.set((target.get() & mask) | u16::from(value) << shift);
+            }
+            0xD => {
+                self.control.set(value);
+                if let Some(eeprom) = &self.eeprom {
+                    // 読み出し中はSDAを解放する
+                    let sda = value & 0x80 != 0 || value & 0x40 != 0;
+                    eeprom.write(value & 0x20 != 0, sda);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+impl Mapper for BandaiFCG {
+    fn cpu_read(&self, addr: usize) -> u8 {
+        match addr {
+            0x4020..=0x5FFF => open_bus(addr),
+            0x6000..=0x7FFF => match &self.eeprom {
+                Some(eeprom) => (eeprom.read() as u8) << 4,
+                None if self.prg_ram_enabled() => self.prg_ram.read(0x2000, 0, addr - 0x6000),
+                None => 0x00,
+            },
+            _ => self.prg.read(0x4000, self.prg_bank(addr), addr & 0x3FFF),
+        }
+    }
+
+    fn cpu_write(&self, addr: usize, value: u8) {
+        match addr {
+            0x6000..=0x7FFF if self.registers.0 => self.write_register(addr & 0x0F, value, false),
+            0x6000..=0x7FFF if self.prg_ram_enabled() => {
+                self.prg_ram.write(0x2000, 0, addr - 0x6000, value)
+            }
+            0x8000..=0xFFFF if self.registers.1 => self.write_register(addr & 0x0F, value, true),
+            _ => (),
+        }
+    }
+
+    fn ppu_read(&self, addr: usize) -> u8 {
+        if self.outer_bank {
+            return self.chr.read(0x2000, 0, addr);
+        }
+        let bank = usize::from(self.chr_banks[addr >> 10].get());
+        self.chr.read(0x0400, bank, addr & 0x03FF)
+    }
+
+    fn ppu_write(&self, addr: usize, value: u8) {
+        if self.outer_bank {
+            self.chr.write(0x2000, 0, addr, value)
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.mirroring.get() {
+            0 => Mirroring::Vertial,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::OneScreenLower,
+            _ => Mirroring::OneScreenUpper,
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending.get()
+    }
+
+    fn prg_ram(&self) -> Option<&BankMemory> {
+        Some(&self.prg_ram)
+    }
+
+    fn chr(&self) -> Option<&BankMemory> {
+        Some(&self.chr)
+    }
+
+    fn state(&self, io: &mut StateIo) {
+        io.cells(&self.chr_banks);
+        io.cell(&self.prg_bank);
+        io.cell(&self.mirroring);
+        io.cell(&self.control);
+        io.cell(&self.irq_enabled);
+        io.cell(&self.irq_latch);
+        io.cell(&self.irq_counter);
+        io.cell(&self.irq_pending);
+        if let Some(eeprom) = &self.eeprom {
+            eeprom.state(io);
+        }
+    }
+
+    /// $0001 -> $0000でIRQ
+    fn cpu_clock(&self, cycle: u32) {
+        if !self.irq_enabled.get() {
+            return;
+        }
+        for _ in 0..cycle {
+            let counter = self.irq_counter.get().wrapping_sub(1);
+            self.irq_counter.set(counter);
+            if counter == 0 {
+                self.irq_pending.set(true);
+            }
+        }
+    }
+
+    /// EEPROM, 153はPRG-RAM
+    fn battery(&self) -> Option<Vec<u8>> {
+        match &self.eeprom {
+            Some(eeprom) => Some(eeprom.dump()),
+            None if self.outer_bank => Some(self.prg_ram.dump()),
+            None => None,
+        }
+    }
+
+    fn load_battery(&self, data: &[u8]) {
+        match &self.eeprom {
+            Some(eeprom) => eeprom.load(data),
+            None => self.prg_ram.load(data),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arch::mapper::bandai::BandaiFCG;
+    use crate::arch::mapper::tests::rom;
+    use crate::arch::mapper::Mapper;
+
+    fn mapper(mapper: u16, submapper: u8) -> BandaiFCG {
+        BandaiFCG::new(rom(mapper, submapper, (0x4000, 32), (0, 0)))
+    }
+
+    /// $800DでSCL, SDAを操作し, $6000の4bitでSDAを読む
+    struct Master<'a>(&'a BandaiFCG);
+
+    impl<'a> Master<'a> {
+        fn write(&self, scl: bool, sda: bool) {
+            self.0
+                .cpu_write(0x800D, (scl as u8) << 5 | (sda as u8) << 6);
+        }
+
+        fn start(&self) {
+            self.write(false, true);
+            self.write(true, true);
+            self.write(true, false);
+            self.write(false, false);
+        }
+
+        fn stop(&self) {
+            self.write(false, false);
+            self.write(true, false);
+            self.write(true, true);
+        }
+
+        fn clock(&self, sda: bool) -> bool {
+            self.write(false, sda);
+            self.write(true, sda);
+            let bit = self.0.cpu_read(0x6000) & 0x10 != 0;
+            self.write(false, sda);
+            bit
+        }
+
+        /// ACKを返す
+        fn send(&self, byte: u8) -> bool {
+            (0..8).for_each(|n| {
+                self.clock(byte & (0x80 >> n) != 0);
+            });
+            !self.clock(true)
+        }
+
+        fn receive(&self) -> u8 {
+            let byte = (0..8).fold(0, |byte, _| byte << 1 | self.clock(true) as u8);
+            // NACK
+            self.clock(true);
+            byte
+        }
+    }
+
+    #[test]
+    fn is_irq_latch() {
+        let mapper = mapper(16, 0);
+        // LZ93D50: latchを書いてから$A
+        mapper.cpu_write(0x800B, 0x03);
+        mapper.cpu_write(0x800C, 0x00);
+        mapper.cpu_clock(5);
+        mapper.cpu_write(0x800A, 0x01);
+        mapper.cpu_clock(2);
+        assert!(!mapper.irq());
+        mapper.cpu_clock(1);
+        assert!(mapper.irq());
+        mapper.cpu_write(0x800A, 0x00);
+        assert!(!mapper.irq());
+
+        // FCG: カウンタに直接書く
+        mapper.cpu_write(0x600B, 0x01);
+        mapper.cpu_write(0x600A, 0x01);
+        mapper.cpu_clock(1);
+        assert!(mapper.irq());
+    }
+
+    #[test]
+    fn is_eeprom_wiring() {
+        let mapper = mapper(16, 5);
+        let master = Master(&mapper);
+        master.start();
+        assert!(master.send(0xA0));
+        assert!(master.send(0x10));
+        assert!(master.send(0x5A));
+        master.stop();
+        assert_eq!(mapper.battery().unwrap()[0x10], 0x5A);
+
+        master.start();
+        assert!(master.send(0xA0));
+        assert!(master.send(0x10));
+        master.start();
+        assert!(master.send(0xA1));
+        assert_eq!(master.receive(), 0x5A);
+        master.stop();
+    }
+
+    #[test]
+    fn is_outer_prg_bank() {
+        let mapper = mapper(153, 0);
+        mapper.cpu_write(0x8008, 0x02);
+        assert_eq!(mapper.prg_bank(0x8000), 0x02);
+        mapper.cpu_write(0x8001, 0x01);
+        assert_eq!(mapper.prg_bank(0x8000), 0x12);
+        assert_eq!(mapper.prg_bank(0xC000), 0x1F);
+    }
+}
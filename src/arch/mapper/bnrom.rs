@@ -0,0 +1,137 @@
+use std::cell::Cell;
+
+use crate::arch::mapper::{open_bus, BankMemory, Mapper};
+use crate::arch::ppu::Mirroring;
+use crate::arch::snapshot::StateIo;
+use crate::parser::INes;
+
+/// Mapper 34の基板
+/// NES 2.0のサブマッパー, なければCHRの有無で判定
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Board {
+    /// $8000-$FFFF PRG 32KB, CHR-RAM 8KB, バスコンフリクトあり
+    BNROM,
+    /// $7FFD PRG 32KB, $7FFE, $7FFF CHR 4KB, PRG-RAM 8KB
+    NINA001,
+}
+
+impl Board {
+    fn from(rom: &INes) -> Board {
+        match rom.submapper() {
+            1 => Board::NINA001,
+            2 => Board::BNROM,
+            _ if rom.chr.len() > 0x2000 => Board::NINA001,
+            _ => Board::BNROM,
+        }
+    }
+}
+
+/// Mapper 34
+pub(crate) struct BNROM {
+    board: Board,
+    prg: BankMemory,
+    chr: BankMemory,
+    prg_ram: BankMemory,
+    mirroring: Mirroring,
+    prg_bank: Cell<u8>,
+    /// NINA-001のみ $0000, $1000
+    chr_banks: [Cell<u8>; 2],
+}
+
+impl BNROM {
+    pub(crate) fn new(rom: INes) -> BNROM {
+        let board = Board::from(&rom);
+        let prg_ram = BankMemory::prg_ram(&rom);
+        let mirroring = rom.mirroring();
+        let chr = BankMemory::chr(rom.chr);
+        BNROM {
+            board,
+            mirroring,
+            prg: BankMemory::rom(rom.prg),
+            chr,
+            prg_ram,
+            prg_bank: Cell::new(0x00),
+            chr_banks: [Cell::new(0x00), Cell::new(0x01)],
+        }
+    }
+}
+
+impl Mapper for BNROM {
+    fn cpu_read(&self, addr: usize) -> u8 {
+        match (self.board, addr) {
+            (_, 0x4020..=0x5FFF) => open_bus(addr),
+            (_, 0x6000..=0x7FFF) => self.prg_ram.read(0x2000, 0, addr - 0x6000),
+            _ => self
+                .prg
+                .read(0x8000, usize::from(self.prg_bank.get()), addr - 0x8000),
+        }
+    }
+
+    fn cpu_write(&self, addr: usize, value: u8) {
+        match (self.board, addr) {
+            (Board::BNROM, 0x6000..=0x7FFF) => self.prg_ram.write(0x2000, 0, addr - 0x6000, value),
+            (Board::BNROM, 0x8000..=0xFFFF) => self.prg_bank.set(value & self.cpu_read(addr)),
+            (Board::NINA001, 0x6000..=0x7FFF) => {
+                // レジスタへの書き込みはRAMにも書かれる
+                self.prg_ram.write(0x2000, 0, addr - 0x6000, value);
+                match addr {
+                    0x7FFD => self.prg_bank.set(value & 0x01),
+                    0x7FFE => self.chr.switch(&self.chr_banks[0], value & 0x0F),
+                    0x7FFF => self.chr.switch(&self.chr_banks[1], value & 0x0F),
+                    _ => (),
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn ppu_read(&self, addr: usize) -> u8 {
+        match self.board {
+            Board::BNROM => self.chr.read(0x2000, 0, addr),
+            Board::NINA001 => {
+                let bank = usize::from(self.chr_banks[addr >> 12].get());
+                self.chr.read(0x1000, bank, addr & 0x0FFF)
+            }
+        }
+    }
+
+    fn ppu_write(&self, addr: usize, value: u8) {
+        if self.board == Board::BNROM {
+            self.chr.write(0x2000, 0, addr, value)
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_ram(&self) -> Option<&BankMemory> {
+        Some(&self.prg_ram)
+    }
+
+    fn chr(&self) -> Option<&BankMemory> {
+        Some(&self.chr)
+    }
+
+    fn state(&self, io: &mut StateIo) {
+        io.cell(&self.prg_bank);
+        io.cells(&self.chr_banks);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arch::mapper::bnrom::{Board, BNROM};
+    use crate::arch::mapper::tests::rom;
+    use crate::arch::mapper::Mapper;
+
+    #[test]
+    fn is_nina001() {
+        let mapper = BNROM::new(rom(34, 0, (0x8000, 2), (0x1000, 4)));
+        assert_eq!(mapper.board, Board::NINA001);
+        mapper.cpu_write(0x7FFF, 0x03);
+        assert_eq!(mapper.ppu_read(0x1000), 3);
+        assert_eq!(mapper.ppu_read(0x0000), 0);
+        assert_eq!(mapper.cpu_read(0x7FFF), 0x03);
+    }
+}
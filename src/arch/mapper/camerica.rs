@@ -0,0 +1,120 @@
+use std::cell::Cell;
+
+use crate::arch::mapper::{open_bus, BankMemory, Mapper};
+use crate::arch::ppu::Mirroring;
+use crate::arch::snapshot::StateIo;
+use crate::parser::INes;
+
+/// Mapper 71 (BF9093/BF9097)
+/// - $9000-$9FFF 4: 1画面ミラーリングの選択 (Fire Hawk)
+/// - $C000-$FFFF PRG 16KB ($8000), $C000-$FFFF 最終バンク固定
+/// - CHR-RAM 8KB
+///
+/// バスコンフリクトなし
+pub(crate) struct Camerica {
+    prg: BankMemory,
+    chr: BankMemory,
+    prg_ram: BankMemory,
+    mirroring: Mirroring,
+    bank: Cell<u8>,
+    /// 書き込まれるまではヘッダのミラーリング
+    one_screen: Cell<Option<u8>>,
+}
+
+impl Camerica {
+    pub(crate) fn new(rom: INes) -> Camerica {
+        let mirroring = rom.mirroring();
+        let prg_ram = BankMemory::prg_ram(&rom);
+        let chr = BankMemory::chr(rom.chr);
+        Camerica {
+            mirroring,
+            prg: BankMemory::rom(rom.prg),
+            chr,
+            prg_ram,
+            bank: Cell::new(0x00),
+            one_screen: Cell::new(None),
+        }
+    }
+}
+
+impl Mapper for Camerica {
+    fn cpu_read(&self, addr: usize) -> u8 {
+        match addr {
+            0x4020..=0x5FFF => open_bus(addr),
+            0x6000..=0x7FFF => self.prg_ram.read(0x2000, 0, addr - 0x6000),
+            0x8000..=0xBFFF => self
+                .prg
+                .read(0x4000, usize::from(self.bank.get()), addr - 0x8000),
+            _ => {
+                let last = (self.prg.len() / 0x4000).max(1) - 1;
+                self.prg.read(0x4000, last, addr - 0xC000)
+            }
+        }
+    }
+
+    fn cpu_write(&self, addr: usize, value: u8) {
+        match addr {
+            0x6000..=0x7FFF => self.prg_ram.write(0x2000, 0, addr - 0x6000, value),
+            0x9000..=0x9FFF => self.one_screen.set(Some(value & 0x10)),
+            0xC000..=0xFFFF => self.bank.set(value & 0x0F),
+            _ => (),
+        }
+    }
+
+    fn ppu_read(&self, addr: usize) -> u8 {
+        self.chr.read(0x2000, 0, addr)
+    }
+
+    fn ppu_write(&self, addr: usize, value: u8) {
+        self.chr.write(0x2000, 0, addr, value)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.one_screen.get() {
+            None => self.mirroring,
+            Some(0x00) => Mirroring::OneScreenLower,
+            Some(_) => Mirroring::OneScreenUpper,
+        }
+    }
+
+    fn prg_ram(&self) -> Option<&BankMemory> {
+        Some(&self.prg_ram)
+    }
+
+    fn chr(&self) -> Option<&BankMemory> {
+        Some(&self.chr)
+    }
+
+    fn state(&self, io: &mut StateIo) {
+        io.cell(&self.bank);
+        io.cell(&self.one_screen);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arch::mapper::camerica::Camerica;
+    use crate::arch::mapper::tests::rom;
+    use crate::arch::mapper::Mapper;
+    use crate::arch::ppu::Mirroring;
+
+    #[test]
+    fn is_last_bank_fixed() {
+        let mapper = Camerica::new(rom(71, 0, (0x4000, 4), (0, 0)));
+        assert_eq!(mapper.cpu_read(0x8000), 0);
+        assert_eq!(mapper.cpu_read(0xC000), 3);
+        mapper.cpu_write(0xC000, 0x02);
+        assert_eq!(mapper.cpu_read(0x8000), 2);
+        assert_eq!(mapper.cpu_read(0xFFFF), 3);
+    }
+
+    #[test]
+    fn is_one_screen_select() {
+        let mapper = Camerica::new(rom(71, 0, (0x4000, 4), (0, 0)));
+        assert_eq!(mapper.mirroring(), Mirroring::Horizontal);
+        mapper.cpu_write(0x9000, 0x10);
+        assert_eq!(mapper.mirroring(), Mirroring::OneScreenUpper);
+        mapper.cpu_write(0x9FFF, 0x00);
+        assert_eq!(mapper.mirroring(), Mirroring::OneScreenLower);
+    }
+}
@@ -0,0 +1,109 @@
+use std::cell::Cell;
+
+use crate::arch::mapper::{open_bus, BankMemory, Mapper};
+use crate::arch::ppu::Mirroring;
+use crate::arch::snapshot::StateIo;
+use crate::parser::INes;
+
+/// Mapper 3
+/// - $8000-$FFFF PRG 16KB/32KB 切り替えなし
+/// - CHR 8KB 切り替え
+///
+/// バスコンフリクトあり: 書き込み値はROMの値とANDされる
+pub(crate) struct CNROM {
+    prg: BankMemory,
+    chr: BankMemory,
+    prg_ram: BankMemory,
+    mirroring: Mirroring,
+    bank: Cell<u8>,
+}
+
+impl CNROM {
+    pub(crate) fn new(rom: INes) -> CNROM {
+        CNROM {
+            prg_ram: BankMemory::prg_ram(&rom),
+            mirroring: rom.mirroring(),
+            prg: BankMemory::rom(rom.prg),
+            chr: BankMemory::chr(rom.chr),
+            bank: Cell::new(0x00),
+        }
+    }
+}
+
+impl Mapper for CNROM {
+    fn cpu_read(&self, addr: usize) -> u8 {
+        match addr {
+            0x4020..=0x5FFF => open_bus(addr),
+            0x6000..=0x7FFF => self.prg_ram.read(0x2000, 0, addr - 0x6000),
+            // 16KBは$C000にミラー
+            _ => self.prg.read(0x8000, 0, addr - 0x8000),
+        }
+    }
+
+    fn cpu_write(&self, addr: usize, value: u8) {
+        match addr {
+            0x6000..=0x7FFF => self.prg_ram.write(0x2000, 0, addr - 0x6000, value),
+            0x8000..=0xFFFF => self.chr.switch(&self.bank, value & self.cpu_read(addr)),
+            _ => (),
+        }
+    }
+
+    fn ppu_read(&self, addr: usize) -> u8 {
+        self.chr.read(0x2000, usize::from(self.bank.get()), addr)
+    }
+
+    fn ppu_write(&self, addr: usize, value: u8) {
+        self.chr
+            .write(0x2000, usize::from(self.bank.get()), addr, value)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_ram(&self) -> Option<&BankMemory> {
+        Some(&self.prg_ram)
+    }
+
+    fn chr(&self) -> Option<&BankMemory> {
+        Some(&self.chr)
+    }
+
+    fn state(&self, io: &mut StateIo) {
+        io.cell(&self.bank);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arch::mapper::cnrom::CNROM;
+    use crate::arch::mapper::tests::rom;
+    use crate::arch::mapper::Mapper;
+
+    #[test]
+    fn is_bus_conflict() {
+        let mut rom = rom(3, 0, (0x4000, 1), (0x2000, 4));
+        rom.prg.iter_mut().for_each(|value| *value = 0xFF);
+        rom.prg[0x0000] = 0x01;
+        let mapper = CNROM::new(rom);
+        mapper.cpu_write(0x8001, 0x02);
+        assert_eq!(mapper.ppu_read(0x0000), 2);
+        // ROMが0x01の番地では0x03 & 0x01
+        mapper.cpu_write(0x8000, 0x03);
+        assert_eq!(mapper.ppu_read(0x0000), 1);
+    }
+
+    #[test]
+    fn is_chr_switched() {
+        let mut rom = rom(3, 0, (0x4000, 1), (0x2000, 4));
+        rom.prg[0x0001] = 0xFF;
+        let mapper = CNROM::new(rom);
+        let chr = mapper.chr().unwrap();
+        mapper.cpu_write(0x8001, 0x02);
+        assert!(chr.take_switched());
+        assert!(!chr.take_switched());
+        // 同じバンクの再選択は切り替えではない
+        mapper.cpu_write(0x8001, 0x02);
+        assert!(!chr.take_switched());
+    }
+}
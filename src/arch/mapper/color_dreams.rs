@@ -0,0 +1,100 @@
+use std::cell::Cell;
+
+use crate::arch::mapper::{open_bus, BankMemory, Mapper};
+use crate::arch::ppu::Mirroring;
+use crate::arch::snapshot::StateIo;
+use crate::parser::INes;
+
+/// Mapper 11
+/// - $8000-$FFFF Write
+///   - 7-4 CHR 8KB 切り替え
+///   - 1-0 PRG 32KB 切り替え
+///
+/// バスコンフリクトあり
+pub(crate) struct ColorDreams {
+    prg: BankMemory,
+    chr: BankMemory,
+    prg_ram: BankMemory,
+    mirroring: Mirroring,
+    bank: Cell<u8>,
+}
+
+impl ColorDreams {
+    pub(crate) fn new(rom: INes) -> ColorDreams {
+        ColorDreams {
+            prg_ram: BankMemory::prg_ram(&rom),
+            mirroring: rom.mirroring(),
+            prg: BankMemory::rom(rom.prg),
+            chr: BankMemory::chr(rom.chr),
+            bank: Cell::new(0x00),
+        }
+    }
+}
+
+impl Mapper for ColorDreams {
+    fn cpu_read(&self, addr: usize) -> u8 {
+        match addr {
+            0x4020..=0x5FFF => open_bus(addr),
+            0x6000..=0x7FFF => self.prg_ram.read(0x2000, 0, addr - 0x6000),
+            _ => self
+                .prg
+                .read(0x8000, usize::from(self.bank.get() & 0x03), addr - 0x8000),
+        }
+    }
+
+    fn cpu_write(&self, addr: usize, value: u8) {
+        match addr {
+            0x6000..=0x7FFF => self.prg_ram.write(0x2000, 0, addr - 0x6000, value),
+            0x8000..=0xFFFF => self.chr.switch(&self.bank, value & self.cpu_read(addr)),
+            _ => (),
+        }
+    }
+
+    fn ppu_read(&self, addr: usize) -> u8 {
+        self.chr
+            .read(0x2000, usize::from(self.bank.get() >> 4), addr)
+    }
+
+    fn ppu_write(&self, addr: usize, value: u8) {
+        self.chr
+            .write(0x2000, usize::from(self.bank.get() >> 4), addr, value)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_ram(&self) -> Option<&BankMemory> {
+        Some(&self.prg_ram)
+    }
+
+    fn chr(&self) -> Option<&BankMemory> {
+        Some(&self.chr)
+    }
+
+    fn state(&self, io: &mut StateIo) {
+        io.cell(&self.bank);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arch::mapper::color_dreams::ColorDreams;
+    use crate::arch::mapper::tests::rom;
+    use crate::arch::mapper::Mapper;
+
+    #[test]
+    fn is_bank_and_bus_conflict() {
+        let mut rom = rom(11, 0, (0x8000, 4), (0x2000, 16));
+        rom.prg[0x0001] = 0xFF;
+        let mapper = ColorDreams::new(rom);
+        // CHR 3, PRG 2
+        mapper.cpu_write(0x8001, 0x32);
+        assert_eq!(mapper.cpu_read(0x8000), 2);
+        assert_eq!(mapper.ppu_read(0x0000), 3);
+        // ROMが0x02の番地では0xF3 & 0x02
+        mapper.cpu_write(0x8000, 0xF3);
+        assert_eq!(mapper.cpu_read(0x8000), 2);
+        assert_eq!(mapper.ppu_read(0x0000), 0);
+    }
+}
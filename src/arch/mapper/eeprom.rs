@@ -0,0 +1,334 @@
+use std::cell::{Cell, RefCell};
+
+use crate::arch::snapshot::StateIo;
+
+/// シリアルEEPROMの型番
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Model {
+    /// 128Byte, デバイスアドレスなし, LSBファースト
+    X24C01,
+    /// 256Byte, デバイスアドレス$A0, MSBファースト
+    C24C02,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Phase {
+    /// STOP後, STARTを待つ
+    Idle,
+    /// 24C01はワードアドレス + R/W, 24C02はデバイスアドレス + R/W
+    Device,
+    /// 24C02のワードアドレス
+    Word,
+    Write,
+    Read,
+    /// 受信したバイトへのACK
+    Ack,
+}
+
+/// I2C接続のEEPROM
+/// SCL=HのSDA立ち下がりでSTART, 立ち上がりでSTOP
+/// SCLの立ち上がりでビットを受け取り, 立ち下がりで出力を変える
+pub(crate) struct Eeprom {
+    model: Model,
+    data: RefCell<Vec<u8>>,
+    phase: Cell<Phase>,
+    /// ACKの後に進む状態
+    next: Cell<Phase>,
+    /// 現在のバイトで受けたクロック数 (ACKを含めて9)
+    bit: Cell<u8>,
+    shift: Cell<u8>,
+    address: Cell<u8>,
+    /// 読み出し中にマスターがACKしたか
+    acked: Cell<bool>,
+    /// EEPROMが出力するSDA (オープンドレイン, trueで解放)
+    output: Cell<bool>,
+    scl: Cell<bool>,
+    sda: Cell<bool>,
+}
+
+impl Eeprom {
+    pub(crate) fn new(model: Model) -> Eeprom {
+        let size = match model {
+            Model::X24C01 => 0x80,
+            Model::C24C02 => 0x100,
+        };
+        Eeprom {
+            model,
+            data: RefCell::new(vec![0x00; size]),
+            phase: Cell::new(Phase::Idle),
+            next: Cell::new(Phase::Idle),
+            bit: Cell::new(0),
+            shift: Cell::new(0x00),
+            address: Cell::new(0x00),
+            acked: Cell::new(false),
+            output: Cell::new(true),
+            scl: Cell::new(false),
+            sda: Cell::new(false),
+        }
+    }
+
+    pub(crate) fn dump(&self) -> Vec<u8> {
+        self.data.borrow().clone()
+    }
+
+    pub(crate) fn load(&self, data: &[u8]) {
+        let mut memory = self.data.borrow_mut();
+        let len = memory.len().min(data.len());
+        memory[..len].copy_from_slice(&data[..len]);
+    }
+
+    /// SDA出力
+    pub(crate) fn read(&self) -> bool {
+        self.output.get()
+    }
+
+    /// マスターのSCL, SDA
+    pub(crate) fn write(&self, scl: bool, sda: bool) {
+        let (prev_scl, prev_sda) = (self.scl.get(), self.sda.get());
+        self.scl.set(scl);
+        self.sda.set(sda);
+        if prev_scl && scl && prev_sda && !sda {
+            self.start();
+        } else if prev_scl && scl && !prev_sda && sda {
+            self.stop();
+        } else if !prev_scl && scl {
+            self.rising(sda);
+        } else if prev_scl && !scl {
+            self.falling();
+        }
+    }
+
+    fn start(&self) {
+        self.phase.set(Phase::Device);
+        self.bit.set(0);
+        self.shift.set(0x00);
+        self.output.set(true);
+    }
+
+    fn stop(&self) {
+        self.phase.set(Phase::Idle);
+        self.output.set(true);
+    }
+
+    fn rising(&self, sda: bool) {
+        let bit = self.bit.get();
+        match self.phase.get() {
+            Phase::Idle => return,
+            Phase::Read if bit == 8 => self.acked.set(!sda),
+            Phase::Read | Phase::Ack => (),
+            _ if bit < 8 => self.shift.set(self.shift.get() << 1 | sda as u8),
+            _ => (),
+        }
+        self.bit.set(bit + 1);
+    }
+
+    fn falling(&self) {
+        let bit = self.bit.get();
+        match self.phase.get() {
+            Phase::Idle => (),
+            Phase::Ack => {
+                self.bit.set(0);
+                self.phase.set(self.next.get());
+                if self.next.get() == Phase::Read {
+                    self.output_bit(0);
+                } else {
+                    self.output.set(true);
+                }
+            }
+            Phase::Read => match bit {
+                1..=7 => self.output_bit(bit),
+                8 => self.output.set(true),
+                // NACKでも次の読み出しは続きのアドレスから
+                _ => {
+                    self.address
+                        .set(self.address.get().wrapping_add(1) & self.mask());
+                    if self.acked.get() {
+                        self.bit.set(0);
+                        self.output_bit(0);
+                    } else {
+                        self.stop();
+                    }
+                }
+            },
+            _ if bit == 8 => {
+                let byte = match self.model {
+                    Model::X24C01 => self.shift.get().reverse_bits(),
+                    Model::C24C02 => self.shift.get(),
+                };
+                self.receive(byte);
+            }
+            _ => (),
+        }
+    }
+
+    fn mask(&self) -> u8 {
+        (self.data.borrow().len() - 1) as u8
+    }
+
+    /// 読み出し中のバイトのn番目の送信ビット
+    fn output_bit(&self, n: u8) {
+        let byte = self.data.borrow()[usize::from(self.address.get())];
+        let shift = match self.model {
+            Model::X24C01 => n,
+            Model::C24C02 => 7 - n,
+        };
+        self.output.set((byte >> shift) & 0x01 != 0);
+    }
+
+    /// 1バイト受信してACK
+    fn receive(&self, byte: u8) {
+        let next = match (self.model, self.phase.get()) {
+            (Model::X24C01, Phase::Device) => {
+                self.address.set(byte & 0x7F);
+                if byte & 0x80 != 0 {
+                    Phase::Read
+                } else {
+                    Phase::Write
+                }
+            }
+            (Model::C24C02, Phase::Device) if byte & 0xF0 != 0xA0 => {
+                // 別デバイス宛てには応答しない
+                self.stop();
+                return;
+            }
+            (Model::C24C02, Phase::Device) if byte & 0x01 != 0 => Phase::Read,
+            (Model::C24C02, Phase::Device) => Phase::Word,
+            (_, Phase::Word) => {
+                self.address.set(byte);
+                Phase::Write
+            }
+            _ => {
+                let address = self.address.get();
+                self.data.borrow_mut()[usize::from(address)] = byte;
+                // ページ内で巡回
+                let page = match self.model {
+                    Model::X24C01 => 0x03,
+                    Model::C24C02 => 0x07,
+                };
+                self.address
+                    .set((address & !page) | (address.wrapping_add(1) & page));
+                Phase::Write
+            }
+        };
+        self.next.set(next);
+        self.phase.set(Phase::Ack);
+        self.output.set(false);
+    }
+
+    pub(crate) fn state(&self, io: &mut StateIo) {
+        io.ref_cell(&self.data);
+        io.cell(&self.phase);
+        io.cell(&self.next);
+        io.cell(&self.bit);
+        io.cell(&self.shift);
+        io.cell(&self.address);
+        io.cell(&self.acked);
+        io.cell(&self.output);
+        io.cell(&self.scl);
+        io.cell(&self.sda);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arch::mapper::eeprom::{Eeprom, Model};
+
+    /// マスター側の操作
+    struct Master<'a>(&'a Eeprom);
+
+    impl<'a> Master<'a> {
+        fn start(&self) {
+            self.0.write(false, true);
+            self.0.write(true, true);
+            self.0.write(true, false);
+            self.0.write(false, false);
+        }
+
+        fn stop(&self) {
+            self.0.write(false, false);
+            self.0.write(true, false);
+            self.0.write(true, true);
+        }
+
+        fn clock(&self, sda: bool) -> bool {
+            self.0.write(false, sda);
+            self.0.write(true, sda);
+            let bit = self.0.read();
+            self.0.write(false, sda);
+            bit
+        }
+
+        /// MSBファースト, ACKを返す
+        fn send(&self, byte: u8) -> bool {
+            (0..8).for_each(|n| {
+                self.clock(byte & (0x80 >> n) != 0);
+            });
+            !self.clock(true)
+        }
+
+        fn receive(&self, ack: bool) -> u8 {
+            let byte = (0..8).fold(0, |byte, _| byte << 1 | self.clock(true) as u8);
+            self.clock(!ack);
+            byte
+        }
+    }
+
+    #[test]
+    fn is_24c02_random_read() {
+        let eeprom = Eeprom::new(Model::C24C02);
+        let master = Master(&eeprom);
+        master.start();
+        assert!(master.send(0xA0));
+        assert!(master.send(0x10));
+        assert!(master.send(0x12));
+        assert!(master.send(0x34));
+        master.stop();
+        assert_eq!(&eeprom.dump()[0x10..0x12], &[0x12, 0x34]);
+
+        // ダミー書き込みでアドレスを設定して連続読み出し
+        master.start();
+        assert!(master.send(0xA0));
+        assert!(master.send(0x10));
+        master.start();
+        assert!(master.send(0xA1));
+        assert_eq!(master.receive(true), 0x12);
+        assert_eq!(master.receive(false), 0x34);
+        master.stop();
+    }
+
+    #[test]
+    fn is_24c02_current_address_read() {
+        let eeprom = Eeprom::new(Model::C24C02);
+        eeprom.load(&[0x12, 0x34, 0x56]);
+        let master = Master(&eeprom);
+        master.start();
+        assert!(master.send(0xA0));
+        assert!(master.send(0x00));
+        master.start();
+        assert!(master.send(0xA1));
+        assert_eq!(master.receive(false), 0x12);
+        master.stop();
+
+        master.start();
+        assert!(master.send(0xA1));
+        assert_eq!(master.receive(false), 0x34);
+        master.stop();
+    }
+
+    #[test]
+    fn is_24c01_lsb_first() {
+        let eeprom = Eeprom::new(Model::X24C01);
+        let master = Master(&eeprom);
+        master.start();
+        // アドレス0x05 + W
+        assert!(master.send(0x05u8.reverse_bits()));
+        assert!(master.send(0x81u8.reverse_bits()));
+        master.stop();
+        assert_eq!(eeprom.dump()[0x05], 0x81);
+
+        master.start();
+        assert!(master.send((0x80 | 0x05u8).reverse_bits()));
+        assert_eq!(master.receive(false).reverse_bits(), 0x81);
+        master.stop();
+    }
+}
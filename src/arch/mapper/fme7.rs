@@ -0,0 +1,382 @@
+use std::cell::Cell;
+
+use crate::arch::mapper::{open_bus, BankMemory, Mapper};
+use crate::arch::ppu::Mirroring;
+use crate::arch::snapshot::StateIo;
+use crate::parser::INes;
+
+/// 1chの最大振幅
+const AUDIO_LEVEL: f32 = 0.15;
+
+/// Sunsoft 5B (AY-3-8910互換)
+/// - $00-$05 矩形波周期 (12bit)
+/// - $06 ノイズ周期 (5bit)
+/// - $07 ミキサー (0: 有効) 5-3: ノイズ, 2-0: 矩形波
+/// - $08-$0A 4: エンベロープ使用, 3-0: 音量
+/// - $0B, $0C エンベロープ周期 (16bit)
+/// - $0D エンベロープ形状 3: continue, 2: attack, 1: alternate, 0: hold
+///
+/// 矩形波はCPU/(32*周期), エンベロープは32段階
+#[derive(Default)]
+struct Sunsoft5B {
+    address: Cell<u8>,
+    registers: [Cell<u8>; 16],
+    tone_counter: [Cell<u32>; 3],
+    tone: [Cell<bool>; 3],
+    noise_counter: Cell<u32>,
+    /// 17bit LFSR
+    noise: Cell<u32>,
+    envelope_counter: Cell<u32>,
+    envelope_step: Cell<u8>,
+    envelope_attack: Cell<bool>,
+    envelope_hold: Cell<bool>,
+}
+
+impl Sunsoft5B {
+    fn select(&self, value: u8) {
+        self.address.set(value & 0x0F);
+    }
+
+    fn write(&self, value: u8) {
+        let address = usize::from(self.address.get());
+        self.registers[address].set(value);
+        // エンベロープ形状の書き込みで再スタート
+        if address == 0x0D {
+            self.envelope_step.set(0);
+            self.envelope_hold.set(false);
+            self.envelope_attack.set(value & 0x04 != 0);
+            self.envelope_counter.set(0);
+        }
+    }
+
+    fn register(&self, idx: usize) -> u32 {
+        u32::from(self.registers[idx].get())
+    }
+
+    fn clock(&self) {
+        for ch in 0..3 {
+            let period = (self.register(ch * 2) | (self.register(ch * 2 + 1) & 0x0F) << 8).max(1);
+            let counter = self.tone_counter[ch].get() + 1;
+            if counter >= period * 16 {
+                self.tone_counter[ch].set(0);
+                self.tone[ch].set(!self.tone[ch].get());
+            } else {
+                self.tone_counter[ch].set(counter);
+            }
+        }
+
+        let period = (self.register(0x06) & 0x1F).max(1);
+        let counter = self.noise_counter.get() + 1;
+        if counter >= period * 32 {
+            self.noise_counter.set(0);
+            let noise = self.noise.get().max(1);
+            let bit = (noise ^ (noise >> 3)) & 0x01;
+            self.noise.set(noise >> 1 | bit << 16);
+        } else {
+            self.noise_counter.set(counter);
+        }
+
+        let period = (self.register(0x0B) | self.register(0x0C) << 8).max(1);
+        let counter = self.envelope_counter.get() + 1;
+        if counter >= period * 16 {
+            self.envelope_counter.set(0);
+            self.clock_envelope();
+        } else {
+            self.envelope_counter.set(counter);
+        }
+    }
+
+    fn clock_envelope(&self) {
+        if self.envelope_hold.get() {
+            return;
+        }
+        if self.envelope_step.get() < 31 {
+            self.envelope_step.set(self.envelope_step.get() + 1);
+            return;
+        }
+        let shape = self.registers[0x0D].get();
+        let (cont, alternate, hold) = (shape & 0x08 != 0, shape & 0x02 != 0, shape & 0x01 != 0);
+        if !cont {
+            // 0で停止
+            self.envelope_hold.set(true);
+            self.envelope_attack.set(false);
+        } else if hold {
+            self.envelope_hold.set(true);
+            if alternate {
+                self.envelope_attack.set(!self.envelope_attack.get());
+            }
+        } else {
+            if alternate {
+                self.envelope_attack.set(!self.envelope_attack.get());
+            }
+            self.envelope_step.set(0);
+        }
+    }
+
+    /// 0-31
+    fn envelope(&self) -> u8 {
+        if self.envelope_attack.get() {
+            self.envelope_step.get()
+        } else {
+            31 - self.envelope_step.get()
+        }
+    }
+
+    /// 各ch最大1.0の合計
+    fn output(&self) -> f32 {
+        let mixer = self.registers[0x07].get();
+        let noise = self.noise.get() & 0x01 != 0;
+        (0..3)
+            .map(|ch| {
+                let tone = self.tone[ch].get() || mixer & (0x01 << ch) != 0;
+                let noise = noise || mixer & (0x08 << ch) != 0;
+                let volume = self.registers[0x08 + ch].get();
+                // 5bit段階, 1段階1.5dB
+                let level = if volume & 0x10 != 0 {
+                    self.envelope()
+                } else {
+                    ((volume & 0x0F) << 1) | (volume & 0x0F != 0) as u8
+                };
+                if !tone || !noise || level == 0 {
+                    0.0
+                } else {
+                    10f32.powf(-f32::from(31 - level) * 1.5 / 20.0)
+                }
+            })
+            .sum()
+    }
+
+    fn state(&self, io: &mut StateIo) {
+        io.cell(&self.address);
+        io.cells(&self.registers);
+        io.cells(&self.tone_counter);
+        io.cells(&self.tone);
+        io.cell(&self.noise_counter);
+        io.cell(&self.noise);
+        io.cell(&self.envelope_counter);
+        io.cell(&self.envelope_step);
+        io.cell(&self.envelope_attack);
+        io.cell(&self.envelope_hold);
+    }
+}
+
+/// Mapper 69
+/// - $8000 コマンド, $A000 パラメータ
+///   - $0-$7 CHR 1KB
+///   - $8 $6000 7: RAM有効, 6: RAM選択, 5-0: バンク
+///   - $9-$B PRG 8KB ($8000, $A000, $C000), $E000-$FFFF 最終バンク固定
+///   - $C mirroring
+///   - $D IRQ 7: カウンタ有効, 0: IRQ有効 (書き込みでacknowledge)
+///   - $E, $F IRQカウンタ 下位, 上位
+/// - $C000, $E000 5B音源 (アドレス, データ)
+///
+/// IRQカウンタはCPUサイクルごとに減算し, $0000 -> $FFFFでIRQ
+pub(crate) struct FME7 {
+    prg: BankMemory,
+    chr: BankMemory,
+    prg_ram: BankMemory,
+    command: Cell<u8>,
+    chr_banks: [Cell<u8>; 8],
+    /// $6000, $8000, $A000, $C000
+    prg_banks: [Cell<u8>; 4],
+    mirroring: Cell<u8>,
+    irq_control: Cell<u8>,
+    irq_counter: Cell<u16>,
+    irq_pending: Cell<bool>,
+    audio: Sunsoft5B,
+}
+
+impl FME7 {
+    pub(crate) fn new(rom: INes) -> FME7 {
+        let prg_ram = BankMemory::prg_ram(&rom);
+        let chr = BankMemory::chr(rom.chr);
+        FME7 {
+            prg: BankMemory::rom(rom.prg),
+            chr,
+            prg_ram,
+            command: Cell::new(0x00),
+            chr_banks: Default::default(),
+            prg_banks: Default::default(),
+            mirroring: Cell::new(0x00),
+            irq_control: Cell::new(0x00),
+            irq_counter: Cell::new(0x0000),
+            irq_pending: Cell::new(false),
+            audio: Sunsoft5B::default(),
+        }
+    }
+
+    /// 8KB単位
+    fn prg_bank(&self, addr: usize) -> usize {
+        match addr {
+            0xE000..=0xFFFF => (self.prg.len() / 0x2000).max(1) - 1,
+            _ => usize::from(self.prg_banks[(addr - 0x6000) >> 13].get() & 0x3F),
+        }
+    }
+
+    fn write_parameter(&self, value: u8) {
+        match self.command.get() & 0x0F {
+            command @ 0x0..=0x7 => self
+                .chr
+                .switch(&self.chr_banks[usize::from(command)], value),
+            command @ 0x8..=0xB => self.prg_banks[usize::from(command) - 0x8].set(value),
+            0xC => self.mirroring.set(value & 0x03),
+            0xD => {
+                self.irq_control.set(value);
+                self.irq_pending.set(false);
+            }
+            0xE => self
+                .irq_counter
+                .set((self.irq_counter.get() & 0xFF00) | u16::from(value)),
+            _ => self
+                .irq_counter
+                .set((self.irq_counter.get() & 0x00FF) | u16::from(value) << 8),
+        }
+    }
+}
+
+impl Mapper for FME7 {
+    fn cpu_read(&self, addr: usize) -> u8 {
+        match addr {
+            0x4020..=0x5FFF => open_bus(addr),
+            0x6000..=0x7FFF => {
+                let bank = self.prg_banks[0].get();
+                match bank & 0xC0 {
+                    // ROM
+                    0x00 | 0x80 => self.prg.read(0x2000, self.prg_bank(addr), addr & 0x1FFF),
+                    0xC0 => self
+                        .prg_ram
+                        .read(0x2000, usize::from(bank & 0x3F), addr & 0x1FFF),
+                    // RAM無効はオープンバス
+                    _ => 0x00,
+                }
+            }
+            _ => self.prg.read(0x2000, self.prg_bank(addr), addr & 0x1FFF),
+        }
+    }
+
+    fn cpu_write(&self, addr: usize, value: u8) {
+        match addr {
+            0x6000..=0x7FFF => {
+                let bank = self.prg_banks[0].get();
+                if bank & 0xC0 == 0xC0 {
+                    self.prg_ram
+                        .write(0x2000, usize::from(bank & 0x3F), addr & 0x1FFF, value);
+                }
+            }
+            0x8000..=0x9FFF => self.command.set(value),
+            0xA000..=0xBFFF => self.write_parameter(value),
+            0xC000..=0xDFFF => self.audio.select(value),
+            0xE000..=0xFFFF => self.audio.write(value),
+            _ => (),
+        }
+    }
+
+    fn ppu_read(&self, addr: usize) -> u8 {
+        let bank = usize::from(self.chr_banks[addr >> 10].get());
+        self.chr.read(0x0400, bank, addr & 0x03FF)
+    }
+
+    fn ppu_write(&self, addr: usize, value: u8) {
+        let bank = usize::from(self.chr_banks[addr >> 10].get());
+        self.chr.write(0x0400, bank, addr & 0x03FF, value)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.mirroring.get() {
+            0 => Mirroring::Vertial,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::OneScreenLower,
+            _ => Mirroring::OneScreenUpper,
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending.get()
+    }
+
+    fn cpu_clock(&self, cycle: u32) {
+        for _ in 0..cycle {
+            let control = self.irq_control.get();
+            if control & 0x80 != 0 {
+                let counter = self.irq_counter.get();
+                if counter == 0 && control & 0x01 != 0 {
+                    self.irq_pending.set(true);
+                }
+                self.irq_counter.set(counter.wrapping_sub(1));
+            }
+            self.audio.clock();
+        }
+    }
+
+    fn audio(&self) -> f32 {
+        self.audio.output() * AUDIO_LEVEL
+    }
+
+    fn prg_ram(&self) -> Option<&BankMemory> {
+        Some(&self.prg_ram)
+    }
+
+    fn chr(&self) -> Option<&BankMemory> {
+        Some(&self.chr)
+    }
+
+    fn state(&self, io: &mut StateIo) {
+        io.cell(&self.command);
+        io.cells(&self.chr_banks);
+        io.cells(&self.prg_banks);
+        io.cell(&self.mirroring);
+        io.cell(&self.irq_control);
+        io.cell(&self.irq_counter);
+        io.cell(&self.irq_pending);
+        self.audio.state(io);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arch::mapper::fme7::{Sunsoft5B, FME7};
+    use crate::arch::mapper::tests::rom;
+    use crate::arch::mapper::Mapper;
+
+    #[test]
+    fn is_irq_counter() {
+        let mapper = FME7::new(rom(69, 0, (0x2000, 4), (0x2000, 1)));
+        let command = |command: u8, value: u8| {
+            mapper.cpu_write(0x8000, command);
+            mapper.cpu_write(0xA000, value);
+        };
+        command(0x0E, 0x02);
+        command(0x0F, 0x00);
+        command(0x0D, 0x81);
+        mapper.cpu_clock(2);
+        assert!(!mapper.irq());
+        mapper.cpu_clock(1);
+        assert!(mapper.irq());
+        command(0x0D, 0x81);
+        assert!(!mapper.irq());
+    }
+
+    #[test]
+    fn is_envelope_shape() {
+        let audio = Sunsoft5B::default();
+        let write = |reg: u8, value: u8| {
+            audio.select(reg);
+            audio.write(value);
+        };
+        write(0x0B, 0x01);
+        // 減衰1回で停止 (\___)
+        write(0x0D, 0x00);
+        assert_eq!(audio.envelope(), 31);
+        (0..16 * 32).for_each(|_| audio.clock());
+        assert_eq!(audio.envelope(), 0);
+        (0..16 * 32).for_each(|_| audio.clock());
+        assert_eq!(audio.envelope(), 0);
+
+        // 三角波 (/\/\)
+        write(0x0D, 0x0E);
+        (0..16 * 31).for_each(|_| audio.clock());
+        assert_eq!(audio.envelope(), 31);
+        (0..16 * 2).for_each(|_| audio.clock());
+        assert_eq!(audio.envelope(), 30);
+    }
+}
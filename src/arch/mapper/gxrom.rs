@@ -0,0 +1,98 @@
+use std::cell::Cell;
+
+use crate::arch::mapper::{open_bus, BankMemory, Mapper};
+use crate::arch::ppu::Mirroring;
+use crate::arch::snapshot::StateIo;
+use crate::parser::INes;
+
+/// Mapper 66
+/// - $8000-$FFFF Write
+///   - 5-4 PRG 32KB 切り替え
+///   - 1-0 CHR 8KB 切り替え
+///
+/// バスコンフリクトあり
+pub(crate) struct GxROM {
+    prg: BankMemory,
+    chr: BankMemory,
+    prg_ram: BankMemory,
+    mirroring: Mirroring,
+    bank: Cell<u8>,
+}
+
+impl GxROM {
+    pub(crate) fn new(rom: INes) -> GxROM {
+        GxROM {
+            prg_ram: BankMemory::prg_ram(&rom),
+            mirroring: rom.mirroring(),
+            prg: BankMemory::rom(rom.prg),
+            chr: BankMemory::chr(rom.chr),
+            bank: Cell::new(0x00),
+        }
+    }
+}
+
+impl Mapper for GxROM {
+    fn cpu_read(&self, addr: usize) -> u8 {
+        match addr {
+            0x4020..=0x5FFF => open_bus(addr),
+            0x6000..=0x7FFF => self.prg_ram.read(0x2000, 0, addr - 0x6000),
+            _ => self.prg.read(
+                0x8000,
+                usize::from((self.bank.get() >> 4) & 0x03),
+                addr - 0x8000,
+            ),
+        }
+    }
+
+    fn cpu_write(&self, addr: usize, value: u8) {
+        match addr {
+            0x6000..=0x7FFF => self.prg_ram.write(0x2000, 0, addr - 0x6000, value),
+            0x8000..=0xFFFF => self.chr.switch(&self.bank, value & self.cpu_read(addr)),
+            _ => (),
+        }
+    }
+
+    fn ppu_read(&self, addr: usize) -> u8 {
+        self.chr
+            .read(0x2000, usize::from(self.bank.get() & 0x03), addr)
+    }
+
+    fn ppu_write(&self, addr: usize, value: u8) {
+        self.chr
+            .write(0x2000, usize::from(self.bank.get() & 0x03), addr, value)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_ram(&self) -> Option<&BankMemory> {
+        Some(&self.prg_ram)
+    }
+
+    fn chr(&self) -> Option<&BankMemory> {
+        Some(&self.chr)
+    }
+
+    fn state(&self, io: &mut StateIo) {
+        io.cell(&self.bank);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arch::mapper::gxrom::GxROM;
+    use crate::arch::mapper::tests::rom;
+    use crate::arch::mapper::Mapper;
+
+    #[test]
+    fn is_prg_chr_bank() {
+        let mut rom = rom(66, 0, (0x8000, 4), (0x2000, 4));
+        // 書き込み先はバスコンフリクトで値が欠けないように
+        rom.prg[0x0001] = 0xFF;
+        let mapper = GxROM::new(rom);
+        mapper.cpu_write(0x8001, 0x21);
+        assert_eq!(mapper.cpu_read(0x8000), 2);
+        assert_eq!(mapper.ppu_read(0x0000), 1);
+    }
+}
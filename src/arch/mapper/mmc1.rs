@@ -0,0 +1,235 @@
+use std::cell::Cell;
+
+use crate::arch::mapper::{open_bus, BankMemory, Mapper};
+use crate::arch::ppu::Mirroring;
+use crate::arch::snapshot::StateIo;
+use crate::parser::INes;
+
+/// Mapper 1
+/// 5回の書き込みで1レジスタを更新するシリアル方式
+/// - $8000 control
+///   - 4 CHR mode (0: 8KB, 1: 4KB x2)
+///   - 3-2 PRG mode (0,1: 32KB, 2: $8000固定, 3: $C000固定)
+///   - 1-0 mirroring
+/// - $A000 CHR bank 0
+/// - $C000 CHR bank 1
+/// - $E000 PRG bank (bit4: PRG-RAM disable)
+///
+/// SUROM/SXROM: CHR bank bit4でPRG 256KB単位を選択
+/// SOROM: CHR bank bit3, SXROM: CHR bank bit2-3でPRG-RAMの8KB単位を選択
+pub(crate) struct MMC1 {
+    prg: BankMemory,
+    chr: BankMemory,
+    prg_ram: BankMemory,
+    /// bit4に番兵を置き, bit0に来たら5回目
+    shift: Cell<u8>,
+    control: Cell<u8>,
+    chr_bank0: Cell<u8>,
+    chr_bank1: Cell<u8>,
+    prg_bank: Cell<u8>,
+}
+
+const SHIFT_RESET: u8 = 0x10;
+
+impl MMC1 {
+    pub(crate) fn new(rom: INes) -> MMC1 {
+        let prg_ram = BankMemory::prg_ram(&rom);
+        // CHRが無ければCHR-RAM
+        let chr = BankMemory::chr(rom.chr);
+        MMC1 {
+            prg: BankMemory::rom(rom.prg),
+            chr,
+            prg_ram,
+            shift: Cell::new(SHIFT_RESET),
+            // 電源投入時は$C000固定
+            control: Cell::new(0x0C),
+            chr_bank0: Cell::new(0x00),
+            chr_bank1: Cell::new(0x00),
+            prg_bank: Cell::new(0x00),
+        }
+    }
+
+    /// 16KB単位のバンク ($8000, $C000)
+    fn prg_banks(&self) -> (usize, usize) {
+        // 512KBのPRGは256KB単位で切り替え
+        let outer = if self.prg.len() > 0x40000 {
+            usize::from(self.chr_bank0.get() & 0x10)
+        } else {
+            0
+        };
+        let bank = usize::from(self.prg_bank.get() & 0x0F) | outer;
+        match (self.control.get() >> 2) & 0x03 {
+            0 | 1 => (bank & !0x01, bank | 0x01),
+            2 => (outer, bank),
+            _ => (bank, outer | 0x0F),
+        }
+    }
+
+    /// 4KB単位のバンク ($0000, $1000)
+    fn chr_banks(&self) -> (usize, usize) {
+        let (bank0, bank1) = (
+            usize::from(self.chr_bank0.get()),
+            usize::from(self.chr_bank1.get()),
+        );
+        if self.control.get() & 0x10 != 0 {
+            (bank0, bank1)
+        } else {
+            (bank0 & !0x01, bank0 | 0x01)
+        }
+    }
+
+    /// 8KB単位
+    fn prg_ram_bank(&self) -> usize {
+        let bank = usize::from(self.chr_bank0.get());
+        match self.prg_ram.len() {
+            0x4000 => (bank >> 3) & 0x01,
+            0x8000 => (bank >> 2) & 0x03,
+            _ => 0,
+        }
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        self.prg_bank.get() & 0x10 == 0
+    }
+
+    fn write_register(&self, addr: usize, value: u8) {
+        match addr {
+            0x8000..=0x9FFF => self.chr.switch(&self.control, value),
+            0xA000..=0xBFFF => self.chr.switch(&self.chr_bank0, value),
+            0xC000..=0xDFFF => self.chr.switch(&self.chr_bank1, value),
+            _ => self.prg_bank.set(value),
+        }
+    }
+}
+
+impl Mapper for MMC1 {
+    fn cpu_read(&self, addr: usize) -> u8 {
+        match addr {
+            0x4020..=0x5FFF => open_bus(addr),
+            0x6000..=0x7FFF if self.prg_ram_enabled() => {
+                self.prg_ram
+                    .read(0x2000, self.prg_ram_bank(), addr - 0x6000)
+            }
+            0x6000..=0x7FFF => 0x00,
+            0x8000..=0xBFFF => self.prg.read(0x4000, self.prg_banks().0, addr - 0x8000),
+            _ => self.prg.read(0x4000, self.prg_banks().1, addr - 0xC000),
+        }
+    }
+
+    fn cpu_write(&self, addr: usize, value: u8) {
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_enabled() => {
+                self.prg_ram
+                    .write(0x2000, self.prg_ram_bank(), addr - 0x6000, value)
+            }
+            0x8000..=0xFFFF => {
+                // bit7でリセット
+                if value & 0x80 != 0 {
+                    self.shift.set(SHIFT_RESET);
+                    self.control.set(self.control.get() | 0x0C);
+                    return;
+                }
+                let shift = self.shift.get();
+                let next = (shift >> 1) | ((value & 0x01) << 4);
+                if shift & 0x01 != 0 {
+                    self.write_register(addr, next);
+                    self.shift.set(SHIFT_RESET);
+                } else {
+                    self.shift.set(next);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn ppu_read(&self, addr: usize) -> u8 {
+        let (bank0, bank1) = self.chr_banks();
+        let bank = if addr < 0x1000 { bank0 } else { bank1 };
+        self.chr.read(0x1000, bank, addr & 0x0FFF)
+    }
+
+    fn ppu_write(&self, addr: usize, value: u8) {
+        let (bank0, bank1) = self.chr_banks();
+        let bank = if addr < 0x1000 { bank0 } else { bank1 };
+        self.chr.write(0x1000, bank, addr & 0x0FFF, value)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.control.get() & 0x03 {
+            0 => Mirroring::OneScreenLower,
+            1 => Mirroring::OneScreenUpper,
+            2 => Mirroring::Vertial,
+            _ => Mirroring::Horizontal,
+        }
+    }
+
+    fn prg_ram(&self) -> Option<&BankMemory> {
+        Some(&self.prg_ram)
+    }
+
+    fn chr(&self) -> Option<&BankMemory> {
+        Some(&self.chr)
+    }
+
+    fn state(&self, io: &mut StateIo) {
+        io.cell(&self.shift);
+        io.cell(&self.control);
+        io.cell(&self.chr_bank0);
+        io.cell(&self.chr_bank1);
+        io.cell(&self.prg_bank);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arch::mapper::mmc1::MMC1;
+    use crate::arch::mapper::tests::rom;
+    use crate::arch::mapper::Mapper;
+    use crate::arch::ppu::Mirroring;
+
+    fn mmc1(prg_banks: usize) -> MMC1 {
+        MMC1::new(rom(1, 0, (0x4000, prg_banks), (0, 0)))
+    }
+
+    fn serial_write(mapper: &MMC1, addr: usize, value: u8) {
+        for bit in 0..5 {
+            mapper.cpu_write(addr, (value >> bit) & 0x01);
+        }
+    }
+
+    #[test]
+    fn is_last_bank_fixed_at_power_on() {
+        let mapper = mmc1(8);
+        serial_write(&mapper, 0xE000, 0x03);
+        assert_eq!(mapper.cpu_read(0x8000), 3);
+        assert_eq!(mapper.cpu_read(0xC000), 7);
+    }
+
+    #[test]
+    fn is_32k_mode_ignore_low_bit() {
+        let mapper = mmc1(8);
+        serial_write(&mapper, 0x8000, 0x02);
+        serial_write(&mapper, 0xE000, 0x05);
+        assert_eq!(mapper.cpu_read(0x8000), 4);
+        assert_eq!(mapper.cpu_read(0xC000), 5);
+        assert_eq!(mapper.mirroring(), Mirroring::Vertial);
+    }
+
+    #[test]
+    fn is_reset_by_bit7() {
+        let mapper = mmc1(8);
+        mapper.cpu_write(0x8000, 0x01);
+        mapper.cpu_write(0x8000, 0x80);
+        serial_write(&mapper, 0xE000, 0x02);
+        assert_eq!(mapper.cpu_read(0x8000), 2);
+    }
+
+    #[test]
+    fn is_surom_outer_bank() {
+        let mapper = mmc1(32);
+        serial_write(&mapper, 0xA000, 0x10);
+        serial_write(&mapper, 0xE000, 0x01);
+        assert_eq!(mapper.cpu_read(0x8000), 17);
+        assert_eq!(mapper.cpu_read(0xC000), 31);
+    }
+}
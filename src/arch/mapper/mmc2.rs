@@ -0,0 +1,200 @@
+use std::cell::Cell;
+
+use crate::arch::mapper::{open_bus, BankMemory, Mapper};
+use crate::arch::ppu::Mirroring;
+use crate::arch::snapshot::StateIo;
+use crate::parser::INes;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Chip {
+    /// Mapper 9: PRG 8KB 切り替え + 末尾24KB固定
+    MMC2,
+    /// Mapper 10: PRG 16KB 切り替え + 末尾16KB固定, PRG-RAM 8KB
+    MMC4,
+}
+
+/// Mapper 9, 10
+/// - $A000 PRG bank
+/// - $B000 CHR $0000 (latch0 = $FD)
+/// - $C000 CHR $0000 (latch0 = $FE)
+/// - $D000 CHR $1000 (latch1 = $FD)
+/// - $E000 CHR $1000 (latch1 = $FE)
+/// - $F000 mirroring (0: vertical, 1: horizontal)
+///
+/// タイル$FD/$FEの取得でラッチが切り替わり, 次の取得からCHRバンクが変わる
+/// MMC2の$0000側は$0FD8/$0FE8のみ, それ以外は行$x8-$xFで反応する
+pub(crate) struct MMC2 {
+    chip: Chip,
+    prg: BankMemory,
+    chr: BankMemory,
+    prg_ram: BankMemory,
+    prg_bank: Cell<u8>,
+    /// [latch0 FD, latch0 FE, latch1 FD, latch1 FE]
+    chr_banks: [Cell<u8>; 4],
+    /// true: $FE
+    latch0: Cell<bool>,
+    latch1: Cell<bool>,
+    mirroring: Cell<Mirroring>,
+}
+
+impl MMC2 {
+    pub(crate) fn new(rom: INes, chip: Chip) -> MMC2 {
+        let mirroring = rom.mirroring();
+        let prg_ram = match chip {
+            Chip::MMC2 => BankMemory::ram(0),
+            Chip::MMC4 => BankMemory::prg_ram(&rom),
+        };
+        MMC2 {
+            chip,
+            prg: BankMemory::rom(rom.prg),
+            chr: BankMemory::chr(rom.chr),
+            prg_ram,
+            prg_bank: Cell::new(0x00),
+            chr_banks: Default::default(),
+            latch0: Cell::new(true),
+            latch1: Cell::new(true),
+            mirroring: Cell::new(mirroring),
+        }
+    }
+
+    /// 8KB単位
+    fn prg_bank(&self, addr: usize) -> usize {
+        let banks = (self.prg.len() / 0x2000).max(4);
+        let bank = usize::from(self.prg_bank.get() & 0x0F);
+        match (self.chip, addr) {
+            (Chip::MMC2, 0x8000..=0x9FFF) => bank,
+            (Chip::MMC2, _) => banks - 4 + ((addr - 0x8000) >> 13),
+            (Chip::MMC4, 0x8000..=0xBFFF) => bank * 2 + ((addr >> 13) & 0x01),
+            (Chip::MMC4, _) => banks - 4 + ((addr - 0x8000) >> 13),
+        }
+    }
+
+    /// 4KB単位
+    fn chr_bank(&self, addr: usize) -> usize {
+        let idx = match (addr < 0x1000, self.latch0.get(), self.latch1.get()) {
+            (true, false, _) => 0,
+            (true, true, _) => 1,
+            (false, _, false) => 2,
+            (false, _, true) => 3,
+        };
+        usize::from(self.chr_banks[idx].get() & 0x1F)
+    }
+}
+
+impl Mapper for MMC2 {
+    fn cpu_read(&self, addr: usize) -> u8 {
+        match addr {
+            0x4020..=0x5FFF => open_bus(addr),
+            0x6000..=0x7FFF => self.prg_ram.read(0x2000, 0, addr - 0x6000),
+            _ => self.prg.read(0x2000, self.prg_bank(addr), addr & 0x1FFF),
+        }
+    }
+
+    fn cpu_write(&self, addr: usize, value: u8) {
+        match addr {
+            0x6000..=0x7FFF => self.prg_ram.write(0x2000, 0, addr - 0x6000, value),
+            0xA000..=0xAFFF => self.prg_bank.set(value),
+            0xB000..=0xEFFF => self
+                .chr
+                .switch(&self.chr_banks[(addr - 0xB000) >> 12], value),
+            0xF000..=0xFFFF => self.mirroring.set(if value & 0x01 != 0 {
+                Mirroring::Horizontal
+            } else {
+                Mirroring::Vertial
+            }),
+            _ => (),
+        }
+    }
+
+    fn ppu_read(&self, addr: usize) -> u8 {
+        self.chr.read(0x1000, self.chr_bank(addr), addr & 0x0FFF)
+    }
+
+    fn ppu_write(&self, addr: usize, value: u8) {
+        self.chr
+            .write(0x1000, self.chr_bank(addr), addr & 0x0FFF, value)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring.get()
+    }
+
+    fn ppu_fetch(&self, addr: usize) {
+        match (self.chip, addr) {
+            (Chip::MMC2, 0x0FD8) | (Chip::MMC4, 0x0FD8..=0x0FDF) => self.latch0.set(false),
+            (Chip::MMC2, 0x0FE8) | (Chip::MMC4, 0x0FE8..=0x0FEF) => self.latch0.set(true),
+            (_, 0x1FD8..=0x1FDF) => self.latch1.set(false),
+            (_, 0x1FE8..=0x1FEF) => self.latch1.set(true),
+            _ => (),
+        }
+    }
+
+    fn prg_ram(&self) -> Option<&BankMemory> {
+        Some(&self.prg_ram)
+    }
+
+    fn chr(&self) -> Option<&BankMemory> {
+        Some(&self.chr)
+    }
+
+    fn state(&self, io: &mut StateIo) {
+        io.cell(&self.prg_bank);
+        io.cells(&self.chr_banks);
+        io.cell(&self.latch0);
+        io.cell(&self.latch1);
+        io.cell(&self.mirroring);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arch::mapper::mmc2::{Chip, MMC2};
+    use crate::arch::mapper::tests::rom;
+    use crate::arch::mapper::Mapper;
+
+    fn mmc2(chip: Chip) -> MMC2 {
+        let mapper = match chip {
+            Chip::MMC2 => 9,
+            Chip::MMC4 => 10,
+        };
+        let mapper = MMC2::new(rom(mapper, 0, (0x2000, 8), (0x1000, 8)), chip);
+        for (idx, addr) in [0xB000, 0xC000, 0xD000, 0xE000].iter().enumerate() {
+            mapper.cpu_write(*addr, idx as u8 + 1);
+        }
+        mapper
+    }
+
+    #[test]
+    fn is_latch_switched_by_fetch() {
+        let mapper = mmc2(Chip::MMC2);
+        assert_eq!(mapper.ppu_read(0x0000), 2);
+        mapper.ppu_fetch(0x0FD8);
+        assert_eq!(mapper.ppu_read(0x0000), 1);
+        mapper.ppu_fetch(0x1FDB);
+        assert_eq!(mapper.ppu_read(0x1000), 3);
+        mapper.ppu_fetch(0x1FE8);
+        assert_eq!(mapper.ppu_read(0x1000), 4);
+    }
+
+    #[test]
+    fn is_chr_ram_banked_write() {
+        let mapper = MMC2::new(rom(10, 0, (0x2000, 8), (0, 0)), Chip::MMC4);
+        mapper.cpu_write(0xC000, 0x01);
+        mapper.ppu_write(0x0000, 0xAB);
+        assert_eq!(mapper.ppu_read(0x0000), 0xAB);
+        assert_eq!(mapper.ppu_read(0x1000), 0x00);
+        mapper.cpu_write(0xE000, 0x01);
+        assert_eq!(mapper.ppu_read(0x1000), 0xAB);
+    }
+
+    #[test]
+    fn is_mmc2_latch0_exact_address() {
+        let mapper = mmc2(Chip::MMC2);
+        mapper.ppu_fetch(0x0FD9);
+        assert_eq!(mapper.ppu_read(0x0000), 2);
+
+        let mapper = mmc2(Chip::MMC4);
+        mapper.ppu_fetch(0x0FD9);
+        assert_eq!(mapper.ppu_read(0x0000), 1);
+    }
+}
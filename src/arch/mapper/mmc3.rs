@@ -0,0 +1,322 @@
+use std::cell::Cell;
+
+use crate::arch::mapper::{open_bus, BankMemory, Mapper};
+use crate::arch::ppu::Mirroring;
+use crate::arch::snapshot::StateIo;
+use crate::parser::INes;
+
+/// A12がこのCPUサイクル数以上Lowだった後の立ち上がりのみ数える
+const A12_FILTER: u32 = 3;
+
+/// IRQカウンタの挙動
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Revision {
+    /// MMC3A: 0への減算, $C001直後の再読込でのみIRQ
+    Old,
+    /// MMC3B/C: カウンタが0なら毎回IRQ
+    New,
+}
+
+/// Mapper 4
+/// - $8000 bank select
+///   - 7 CHR inversion ($0000と$1000を入れ替え)
+///   - 6 PRG mode (0: $8000切り替え, 1: $C000切り替え)
+///   - 2-0 R0-R7
+/// - $8001 bank data
+/// - $A000 mirroring (0: vertical, 1: horizontal)
+/// - $A001 PRG-RAM (7: enable, 6: write protect)
+/// - $C000 IRQ latch
+/// - $C001 IRQ reload
+/// - $E000 IRQ disable, acknowledge
+/// - $E001 IRQ enable
+///
+/// A12の立ち上がりでIRQカウンタを減算
+/// BGとスプライトのパターンテーブルが異なれば1スキャンライン1回になる
+pub(crate) struct MMC3 {
+    prg: BankMemory,
+    chr: BankMemory,
+    prg_ram: BankMemory,
+    bank_select: Cell<u8>,
+    banks: [Cell<u8>; 8],
+    mirroring: Cell<Mirroring>,
+    prg_ram_protect: Cell<u8>,
+    irq_latch: Cell<u8>,
+    irq_counter: Cell<u8>,
+    irq_reload: Cell<bool>,
+    irq_enabled: Cell<bool>,
+    irq_pending: Cell<bool>,
+    revision: Revision,
+    a12: Cell<bool>,
+    /// A12がLowになってからのCPUサイクル数
+    a12_low: Cell<u32>,
+}
+
+impl MMC3 {
+    pub(crate) fn new(rom: INes) -> MMC3 {
+        let mirroring = rom.mirroring();
+        let prg_ram = BankMemory::prg_ram(&rom);
+        // NES 2.0 submapper 4 はMMC3A
+        let revision = if rom.submapper() == 4 {
+            Revision::Old
+        } else {
+            Revision::New
+        };
+        let chr = BankMemory::chr(rom.chr);
+        MMC3 {
+            prg: BankMemory::rom(rom.prg),
+            chr,
+            prg_ram,
+            bank_select: Cell::new(0x00),
+            banks: Default::default(),
+            mirroring: Cell::new(mirroring),
+            prg_ram_protect: Cell::new(0x80),
+            irq_latch: Cell::new(0x00),
+            irq_counter: Cell::new(0x00),
+            irq_reload: Cell::new(false),
+            irq_enabled: Cell::new(false),
+            irq_pending: Cell::new(false),
+            revision,
+            a12: Cell::new(false),
+            a12_low: Cell::new(0),
+        }
+    }
+
+    /// 8KB単位
+    fn prg_bank(&self, addr: usize) -> usize {
+        let last = (self.prg.len() / 0x2000).max(2) - 1;
+        let swap = self.bank_select.get() & 0x40 != 0;
+        match (addr >> 13) & 0x03 {
+            0 if swap => last - 1,
+            0 => usize::from(self.banks[6].get()),
+            1 => usize::from(self.banks[7].get()),
+            2 if swap => usize::from(self.banks[6].get()),
+            2 => last - 1,
+            _ => last,
+        }
+    }
+
+    /// 1KB単位
+    fn chr_bank(&self, addr: usize) -> usize {
+        // inversion時は前後半を入れ替え
+        let addr = if self.bank_select.get() & 0x80 != 0 {
+            addr ^ 0x1000
+        } else {
+            addr
+        };
+        let slot = addr >> 10;
+        match slot {
+            // 2KB
+            0..=3 => usize::from(self.banks[slot / 2].get() & 0xFE) | (slot & 0x01),
+            _ => usize::from(self.banks[slot - 2].get()),
+        }
+    }
+
+    fn clock_irq(&self) {
+        let counter = self.irq_counter.get();
+        let reload = self.irq_reload.get();
+        let next = if counter == 0 || reload {
+            self.irq_latch.get()
+        } else {
+            counter - 1
+        };
+        self.irq_counter.set(next);
+        self.irq_reload.set(false);
+
+        let fire = match self.revision {
+            Revision::Old => next == 0 && (counter != 0 || reload),
+            Revision::New => next == 0,
+        };
+        if fire && self.irq_enabled.get() {
+            self.irq_pending.set(true);
+        }
+    }
+}
+
+impl Mapper for MMC3 {
+    fn cpu_read(&self, addr: usize) -> u8 {
+        match addr {
+            0x4020..=0x5FFF => open_bus(addr),
+            0x6000..=0x7FFF if self.prg_ram_protect.get() & 0x80 != 0 => {
+                self.prg_ram.read(0x2000, 0, addr - 0x6000)
+            }
+            0x6000..=0x7FFF => 0x00,
+            _ => self.prg.read(0x2000, self.prg_bank(addr), addr & 0x1FFF),
+        }
+    }
+
+    fn cpu_write(&self, addr: usize, value: u8) {
+        match addr & 0xE001 {
+            0x6000 | 0x6001 if self.prg_ram_protect.get() & 0xC0 == 0x80 => {
+                self.prg_ram.write(0x2000, 0, addr - 0x6000, value)
+            }
+            0x8000 => {
+                let inversion = (self.bank_select.replace(value) ^ value) & 0x80 != 0;
+                if inversion {
+                    self.chr.set_switched();
+                }
+            }
+            0x8001 => match usize::from(self.bank_select.get() & 0x07) {
+                idx @ 0..=5 => self.chr.switch(&self.banks[idx], value),
+                idx => self.banks[idx].set(value),
+            },
+            0xA000 => self.mirroring.set(if value & 0x01 != 0 {
+                Mirroring::Horizontal
+            } else {
+                Mirroring::Vertial
+            }),
+            0xA001 => self.prg_ram_protect.set(value),
+            0xC000 => self.irq_latch.set(value),
+            0xC001 => {
+                self.irq_counter.set(0x00);
+                self.irq_reload.set(true);
+            }
+            0xE000 => {
+                self.irq_enabled.set(false);
+                self.irq_pending.set(false);
+            }
+            0xE001 => self.irq_enabled.set(true),
+            _ => (),
+        }
+    }
+
+    fn ppu_read(&self, addr: usize) -> u8 {
+        self.chr.read(0x0400, self.chr_bank(addr), addr & 0x03FF)
+    }
+
+    fn ppu_write(&self, addr: usize, value: u8) {
+        self.chr
+            .write(0x0400, self.chr_bank(addr), addr & 0x03FF, value)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring.get()
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending.get()
+    }
+
+    fn cpu_clock(&self, cycle: u32) {
+        if !self.a12.get() {
+            self.a12_low.set(self.a12_low.get().saturating_add(cycle));
+        }
+    }
+
+    /// ネームテーブル取得などの短いLowは無視する
+    fn ppu_fetch(&self, addr: usize) {
+        let a12 = addr & 0x1000 != 0;
+        match (self.a12.get(), a12) {
+            (false, true) if self.a12_low.get() >= A12_FILTER => self.clock_irq(),
+            (true, false) => self.a12_low.set(0),
+            _ => (),
+        }
+        self.a12.set(a12);
+    }
+
+    fn prg_ram(&self) -> Option<&BankMemory> {
+        Some(&self.prg_ram)
+    }
+
+    fn chr(&self) -> Option<&BankMemory> {
+        Some(&self.chr)
+    }
+
+    fn state(&self, io: &mut StateIo) {
+        io.cell(&self.bank_select);
+        io.cells(&self.banks);
+        io.cell(&self.mirroring);
+        io.cell(&self.prg_ram_protect);
+        io.cell(&self.irq_latch);
+        io.cell(&self.irq_counter);
+        io.cell(&self.irq_reload);
+        io.cell(&self.irq_enabled);
+        io.cell(&self.irq_pending);
+        io.cell(&self.a12);
+        io.cell(&self.a12_low);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arch::mapper::mmc3::{Revision, MMC3};
+    use crate::arch::mapper::tests::rom;
+    use crate::arch::mapper::Mapper;
+
+    fn mmc3(revision: Revision) -> MMC3 {
+        let submapper = if revision == Revision::Old { 4 } else { 0 };
+        MMC3::new(rom(4, submapper, (0x2000, 8), (0x2000, 1)))
+    }
+
+    /// BG $0000, スプライト $1000の1ライン
+    fn line(mapper: &MMC3) {
+        mapper.cpu_clock(86);
+        mapper.ppu_fetch(0x1FF0);
+        mapper.cpu_clock(21);
+        mapper.ppu_fetch(0x0000);
+    }
+
+    #[test]
+    fn is_prg_mode_swap() {
+        let mapper = mmc3(Revision::New);
+        mapper.cpu_write(0x8000, 0x06);
+        mapper.cpu_write(0x8001, 0x02);
+        assert_eq!(mapper.cpu_read(0x8000), 2);
+        assert_eq!(mapper.cpu_read(0xC000), 6);
+        mapper.cpu_write(0x8000, 0x46);
+        assert_eq!(mapper.cpu_read(0x8000), 6);
+        assert_eq!(mapper.cpu_read(0xC000), 2);
+        assert_eq!(mapper.cpu_read(0xE000), 7);
+    }
+
+    #[test]
+    fn is_irq_after_latch_lines() {
+        let mapper = mmc3(Revision::New);
+        mapper.cpu_write(0xC000, 0x02);
+        mapper.cpu_write(0xC001, 0x00);
+        mapper.cpu_write(0xE001, 0x00);
+        // 再読込 -> 1 -> 0
+        line(&mapper);
+        line(&mapper);
+        assert!(!mapper.irq());
+        line(&mapper);
+        assert!(mapper.irq());
+        mapper.cpu_write(0xE000, 0x00);
+        assert!(!mapper.irq());
+    }
+
+    #[test]
+    fn is_zero_latch_by_revision() {
+        for (revision, expect) in [(Revision::Old, false), (Revision::New, true)].iter() {
+            let mapper = mmc3(*revision);
+            mapper.cpu_write(0xE001, 0x00);
+            // $C001直後は両方IRQ
+            mapper.cpu_write(0xC001, 0x00);
+            line(&mapper);
+            assert!(mapper.irq());
+            mapper.cpu_write(0xE000, 0x00);
+            mapper.cpu_write(0xE001, 0x00);
+            // 以降はMMC3B/Cのみ
+            line(&mapper);
+            assert_eq!(mapper.irq(), *expect);
+        }
+    }
+
+    #[test]
+    fn is_a12_filter() {
+        let mapper = mmc3(Revision::New);
+        mapper.cpu_write(0xC000, 0x05);
+        mapper.cpu_write(0xC001, 0x00);
+        // BG $1000, スプライト $0000: 次のラインのBG取得で立ち上がる
+        mapper.cpu_clock(21);
+        mapper.ppu_fetch(0x1000);
+        assert_eq!(mapper.irq_counter.get(), 5);
+        // タイルごとのネームテーブル取得は無視
+        mapper.ppu_fetch(0x2000);
+        mapper.ppu_fetch(0x1000);
+        assert_eq!(mapper.irq_counter.get(), 5);
+        mapper.ppu_fetch(0x0FF0);
+        mapper.cpu_clock(21);
+        mapper.ppu_fetch(0x1000);
+        assert_eq!(mapper.irq_counter.get(), 4);
+    }
+}
@@ -0,0 +1,563 @@
+use std::cell::{Cell, RefCell};
+
+use crate::arch::apu::{pulse_level, Pulse, CPU_CLOCK};
+use crate::arch::mapper::{open_bus, BankMemory, Mapper};
+use crate::arch::ppu::Mirroring;
+use crate::arch::snapshot::StateIo;
+use crate::parser::INes;
+
+/// 矩形波のエンベロープ, 長さカウンタは240Hz固定
+const FRAME_PERIOD: u32 = CPU_CLOCK / 240;
+
+/// Mapper 5
+/// - $5000-$5015 拡張音源 (矩形波x2, PCM)
+/// - $5100 PRG mode, $5101 CHR mode
+/// - $5102, $5103 PRG-RAM protect (2, 1で書き込み可)
+/// - $5104 ExRAM mode
+///   - 0 ネームテーブル, 1 拡張属性, 2 CPU RAM, 3 CPU ROM
+/// - $5105 ネームテーブル (2bitずつ: 0 CIRAM $2000, 1 CIRAM $2400, 2 ExRAM, 3 fill)
+/// - $5106, $5107 fill タイル, 属性
+/// - $5113-$5117 PRG bank (bit7: ROM)
+/// - $5120-$5127 CHR A (スプライト), $5128-$512B CHR B (BG), $5130 上位bit
+/// - $5200-$5202 縦分割 (有効/左右/境界タイル, スクロール, CHR bank)
+/// - $5203, $5204 スキャンラインIRQ
+/// - $5205, $5206 8x8乗算器
+/// - $5C00-$5FFF ExRAM 1KB
+pub(crate) struct MMC5 {
+    prg: BankMemory,
+    chr: BankMemory,
+    prg_ram: BankMemory,
+    exram: RefCell<[u8; 0x400]>,
+
+    prg_mode: Cell<u8>,
+    /// $5113-$5117
+    prg_banks: [Cell<u8>; 5],
+    prg_ram_protect: [Cell<u8>; 2],
+
+    chr_mode: Cell<u8>,
+    chr_a: [Cell<usize>; 8],
+    chr_b: [Cell<usize>; 4],
+    chr_upper: Cell<u8>,
+    /// 8x16スプライト無効時は最後に書き込んだ側を使う
+    chr_b_last: Cell<bool>,
+    sprite16: Cell<bool>,
+
+    exram_mode: Cell<u8>,
+    nametable: Cell<u8>,
+    fill_tile: Cell<u8>,
+    fill_attr: Cell<u8>,
+    split_control: Cell<u8>,
+    split_scroll: Cell<u8>,
+    split_bank: Cell<u8>,
+    /// 直前のネームテーブル取得で決まったBGの4KB CHR bank, タイル, 属性
+    /// 拡張属性モード, 分割領域で使う
+    fetch_bank: Cell<Option<usize>>,
+    fetch_tile: Cell<Option<u8>>,
+    fetch_attr: Cell<Option<u8>>,
+
+    irq_compare: Cell<u8>,
+    irq_enabled: Cell<bool>,
+    irq_pending: Cell<bool>,
+    in_frame: Cell<bool>,
+
+    multiplicand: Cell<u8>,
+    multiplier: Cell<u8>,
+
+    pulse: [Pulse; 2],
+    /// CPU 2サイクルでAPU 1サイクル
+    apu_parity: Cell<bool>,
+    frame_cycle: Cell<u32>,
+    pcm: Cell<u8>,
+    /// bit0: 読み込みモード, bit7: IRQ有効
+    pcm_control: Cell<u8>,
+    pcm_irq: Cell<bool>,
+}
+
+impl MMC5 {
+    pub(crate) fn new(rom: INes) -> MMC5 {
+        let prg_ram = BankMemory::prg_ram(&rom);
+        let chr = BankMemory::chr(rom.chr);
+        MMC5 {
+            prg: BankMemory::rom(rom.prg),
+            chr,
+            prg_ram,
+            exram: RefCell::new([0x00; 0x400]),
+            // 電源投入時は8KBx4, $E000に最終バンク
+            prg_mode: Cell::new(0x03),
+            prg_banks: [
+                Cell::new(0x00),
+                Cell::new(0x00),
+                Cell::new(0x00),
+                Cell::new(0x00),
+                Cell::new(0xFF),
+            ],
+            prg_ram_protect: Default::default(),
+            chr_mode: Cell::new(0x00),
+            chr_a: Default::default(),
+            chr_b: Default::default(),
+            chr_upper: Cell::new(0x00),
+            chr_b_last: Cell::new(false),
+            sprite16: Cell::new(false),
+            exram_mode: Cell::new(0x00),
+            nametable: Cell::new(0x00),
+            fill_tile: Cell::new(0x00),
+            fill_attr: Cell::new(0x00),
+            split_control: Cell::new(0x00),
+            split_scroll: Cell::new(0x00),
+            split_bank: Cell::new(0x00),
+            fetch_bank: Cell::new(None),
+            fetch_tile: Cell::new(None),
+            fetch_attr: Cell::new(None),
+            irq_compare: Cell::new(0x00),
+            irq_enabled: Cell::new(false),
+            irq_pending: Cell::new(false),
+            in_frame: Cell::new(false),
+            multiplicand: Cell::new(0xFF),
+            multiplier: Cell::new(0xFF),
+            pulse: Default::default(),
+            apu_parity: Cell::new(false),
+            frame_cycle: Cell::new(0),
+            pcm: Cell::new(0x00),
+            pcm_control: Cell::new(0x00),
+            pcm_irq: Cell::new(false),
+        }
+    }
+
+    /// ($5113-$5117の値, 8KB単位のバンク数)
+    fn prg_register(&self, addr: usize) -> (u8, usize) {
+        let bank = |idx: usize| self.prg_banks[idx].get();
+        match (self.prg_mode.get() & 0x03, addr) {
+            // $6000は常にRAM
+            (_, 0x6000..=0x7FFF) => (bank(0) & 0x7F, 1),
+            // $5117は常にROM
+            (0, _) => (bank(4) | 0x80, 4),
+            (1, 0x8000..=0xBFFF) | (2, 0x8000..=0xBFFF) => (bank(2), 2),
+            (1, _) => (bank(4) | 0x80, 2),
+            (2, 0xC000..=0xDFFF) => (bank(3), 1),
+            (2, _) => (bank(4) | 0x80, 1),
+            (_, 0xE000..=0xFFFF) => (bank(4) | 0x80, 1),
+            (_, _) => (bank(1 + ((addr - 0x8000) >> 13)), 1),
+        }
+    }
+
+    /// (ROMか, 8KB単位のバンク)
+    fn prg_bank(&self, addr: usize) -> (bool, usize) {
+        let (register, size) = self.prg_register(addr);
+        let bank = usize::from(register & 0x7F) & !(size - 1) | ((addr >> 13) & (size - 1));
+        (register & 0x80 != 0, bank)
+    }
+
+    fn prg_ram_writable(&self) -> bool {
+        self.prg_ram_protect[0].get() & 0x03 == 0x02 && self.prg_ram_protect[1].get() & 0x03 == 0x01
+    }
+
+    /// (バンクサイズ, バンク)
+    fn chr_bank(&self, addr: usize) -> (usize, usize) {
+        // 8x16スプライト時, 描画中のBG取得はB
+        let use_b = if self.sprite16.get() {
+            self.in_frame.get()
+        } else {
+            self.chr_b_last.get()
+        };
+        let slot = addr >> 10;
+        let (a, b) = (
+            |idx: usize| self.chr_a[idx].get(),
+            |idx: usize| self.chr_b[idx].get(),
+        );
+        match (self.chr_mode.get() & 0x03, use_b) {
+            (0, false) => (0x2000, a(7)),
+            (0, true) => (0x2000, b(3)),
+            (1, false) => (0x1000, a(3 + (slot & 0x04))),
+            (1, true) => (0x1000, b(3)),
+            (2, false) => (0x0800, a(1 + (slot & 0x06))),
+            (2, true) => (0x0800, b(1 + (slot & 0x02))),
+            (_, false) => (0x0400, a(slot)),
+            (_, true) => (0x0400, b(slot & 0x03)),
+        }
+    }
+
+    /// 縦分割の領域内か
+    fn is_split(&self, column: usize) -> bool {
+        let control = self.split_control.get();
+        if control & 0x80 == 0 || self.exram_mode.get() > 1 || !self.in_frame.get() {
+            return false;
+        }
+        let threshold = usize::from(control & 0x1F);
+        if control & 0x40 == 0 {
+            column < threshold
+        } else {
+            column >= threshold
+        }
+    }
+
+    /// BGのネームテーブル取得
+    /// 分割領域のタイル, 拡張属性モードのCHR bankと属性を記録する
+    fn fetch_name(&self, offset: usize) {
+        let exram = self.exram.borrow();
+        let column = offset % 32;
+        if self.is_split(column) {
+            let row = (offset / 32 + usize::from(self.split_scroll.get()) / 8) % 30;
+            let attr = exram[0x3C0 + row / 4 * 8 + column / 4];
+            let shift = (row & 0x02) * 2 + (column & 0x02);
+            self.fetch_bank
+                .set(Some(usize::from(self.split_bank.get())));
+            self.fetch_tile.set(Some(exram[row * 32 + column]));
+            self.fetch_attr.set(Some(((attr >> shift) & 0x03) * 0x55));
+            return;
+        }
+        self.fetch_tile.set(None);
+        if self.exram_mode.get() == 1 {
+            let ex = exram[offset];
+            let bank = usize::from(ex & 0x3F) | usize::from(self.chr_upper.get() & 0x03) << 6;
+            self.fetch_bank.set(Some(bank));
+            self.fetch_attr.set(Some((ex >> 6) * 0x55));
+        } else {
+            self.fetch_bank.set(None);
+            self.fetch_attr.set(None);
+        }
+    }
+
+    fn write_register(&self, addr: usize, value: u8) {
+        match addr {
+            0x5000..=0x5003 => self.pulse[0].write(addr & 0x03, value),
+            0x5004..=0x5007 => self.pulse[1].write(addr & 0x03, value),
+            0x5010 => self.pcm_control.set(value),
+            // 0は書き込めない
+            0x5011 if self.pcm_control.get() & 0x01 == 0 && value != 0 => self.pcm.set(value),
+            0x5015 => {
+                self.pulse[0].set_enabled(value & 0x01 != 0);
+                self.pulse[1].set_enabled(value & 0x02 != 0);
+            }
+            0x5100 => self.prg_mode.set(value),
+            0x5101 => self.chr.switch(&self.chr_mode, value),
+            0x5102 | 0x5103 => self.prg_ram_protect[addr - 0x5102].set(value),
+            0x5104 => self.exram_mode.set(value & 0x03),
+            0x5105 => self.nametable.set(value),
+            0x5106 => self.fill_tile.set(value),
+            0x5107 => self.fill_attr.set(value & 0x03),
+            0x5113..=0x5117 => self.prg_banks[addr - 0x5113].set(value),
+            0x5120..=0x5127 => {
+                let bank = usize::from(value) | usize::from(self.chr_upper.get() & 0x03) << 8;
+                self.chr.switch(&self.chr_a[addr - 0x5120], bank);
+                self.chr.switch(&self.chr_b_last, false);
+            }
+            0x5128..=0x512B => {
+                let bank = usize::from(value) | usize::from(self.chr_upper.get() & 0x03) << 8;
+                self.chr.switch(&self.chr_b[addr - 0x5128], bank);
+                self.chr.switch(&self.chr_b_last, true);
+            }
+            0x5130 => self.chr_upper.set(value),
+            0x5200 => self.split_control.set(value),
+            0x5201 => self.split_scroll.set(value),
+            0x5202 => self.split_bank.set(value),
+            0x5203 => self.irq_compare.set(value),
+            0x5204 => self.irq_enabled.set(value & 0x80 != 0),
+            0x5205 => self.multiplicand.set(value),
+            0x5206 => self.multiplier.set(value),
+            0x5C00..=0x5FFF => {
+                // ネームテーブル用途では描画中以外0が書き込まれる
+                let value = match self.exram_mode.get() {
+                    0 | 1 if !self.in_frame.get() => 0x00,
+                    3 => return,
+                    _ => value,
+                };
+                self.exram.borrow_mut()[addr - 0x5C00] = value;
+            }
+            _ => (),
+        }
+    }
+
+    fn read_register(&self, addr: usize) -> u8 {
+        match addr {
+            0x5010 => {
+                let irq = self.pcm_irq.get();
+                self.pcm_irq.set(false);
+                (irq as u8) << 7 | (self.pcm_control.get() & 0x01)
+            }
+            0x5015 => (self.pulse[0].is_active() as u8) | (self.pulse[1].is_active() as u8) << 1,
+            0x5204 => {
+                let pending = self.irq_pending.get();
+                self.irq_pending.set(false);
+                (pending as u8) << 7 | (self.in_frame.get() as u8) << 6
+            }
+            0x5205 => (u16::from(self.multiplicand.get()) * u16::from(self.multiplier.get())) as u8,
+            0x5206 => {
+                ((u16::from(self.multiplicand.get()) * u16::from(self.multiplier.get())) >> 8) as u8
+            }
+            0x5C00..=0x5FFF if self.exram_mode.get() >= 2 => self.exram.borrow()[addr - 0x5C00],
+            // オープンバス
+            _ => 0x00,
+        }
+    }
+
+    /// 読み込みモードでは$8000-$BFFFの読み出しをPCMに出力
+    fn pcm_read(&self, addr: usize, value: u8) {
+        if self.pcm_control.get() & 0x01 == 0 || !(0x8000..=0xBFFF).contains(&addr) {
+            return;
+        }
+        if value == 0 {
+            if self.pcm_control.get() & 0x80 != 0 {
+                self.pcm_irq.set(true);
+            }
+        } else {
+            self.pcm.set(value);
+        }
+    }
+}
+
+impl Mapper for MMC5 {
+    fn cpu_read(&self, addr: usize) -> u8 {
+        match addr {
+            0x4020..=0x4FFF => open_bus(addr),
+            0x5000..=0x5FFF => self.read_register(addr),
+            _ => {
+                let (rom, bank) = self.prg_bank(addr);
+                let value = if rom {
+                    self.prg.read(0x2000, bank, addr & 0x1FFF)
+                } else {
+                    self.prg_ram.read(0x2000, bank, addr & 0x1FFF)
+                };
+                self.pcm_read(addr, value);
+                value
+            }
+        }
+    }
+
+    fn cpu_write(&self, addr: usize, value: u8) {
+        match addr {
+            0x5000..=0x5FFF => self.write_register(addr, value),
+            0x6000..=0xDFFF if self.prg_ram_writable() => {
+                let (rom, bank) = self.prg_bank(addr);
+                if !rom {
+                    self.prg_ram.write(0x2000, bank, addr & 0x1FFF, value);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn ppu_read(&self, addr: usize) -> u8 {
+        if let Some(bank) = self.fetch_bank.get() {
+            return self.chr.read(0x1000, bank, addr & 0x0FFF);
+        }
+        let (size, bank) = self.chr_bank(addr);
+        self.chr.read(size, bank, addr & (size - 1))
+    }
+
+    fn ppu_write(&self, addr: usize, value: u8) {
+        let (size, bank) = self.chr_bank(addr);
+        self.chr.write(size, bank, addr & (size - 1), value)
+    }
+
+    /// ExRAM, fillのページはnametable_readで横取りする
+    fn mirroring(&self) -> Mirroring {
+        let nametable = self.nametable.get();
+        let page = |table: usize| usize::from((nametable >> (table * 2)) & 0x01);
+        Mirroring::Quadrant([page(0), page(1), page(2), page(3)])
+    }
+
+    /// 描画中の取得ではppu_fetchで記録したタイル, 属性を返す
+    fn nametable_read(&self, addr: usize) -> Option<u8> {
+        let offset = addr & 0x03FF;
+        let fetched = if offset < 0x3C0 {
+            self.fetch_tile.get()
+        } else {
+            self.fetch_attr.get()
+        };
+        if fetched.is_some() {
+            return fetched;
+        }
+
+        let table = (addr >> 10) & 0x03;
+        match (self.nametable.get() >> (table * 2)) & 0x03 {
+            2 if self.exram_mode.get() <= 1 => Some(self.exram.borrow()[offset]),
+            2 => Some(0x00),
+            3 if offset < 0x3C0 => Some(self.fill_tile.get()),
+            3 => Some(self.fill_attr.get() * 0x55),
+            _ => None,
+        }
+    }
+
+    fn nametable_write(&self, addr: usize, value: u8) -> bool {
+        let table = (addr >> 10) & 0x03;
+        match (self.nametable.get() >> (table * 2)) & 0x03 {
+            2 => {
+                if self.exram_mode.get() <= 1 {
+                    self.exram.borrow_mut()[addr & 0x03FF] = value;
+                }
+                true
+            }
+            3 => true,
+            _ => false,
+        }
+    }
+
+    /// 属性テーブルを除くネームテーブルの取得で分割, 拡張属性を決める
+    fn ppu_fetch(&self, addr: usize) {
+        if (0x2000..=0x2FFF).contains(&addr) && addr & 0x03FF < 0x3C0 {
+            self.fetch_name(addr & 0x03FF);
+        }
+    }
+
+    fn ppu_register_write(&self, addr: usize, value: u8) {
+        match addr {
+            0x2000 => self.sprite16.set(value & 0x20 != 0),
+            // 描画無効でフレーム外扱い
+            0x2001 if value & 0x18 == 0 => self.in_frame.set(false),
+            _ => (),
+        }
+    }
+
+    fn irq(&self) -> bool {
+        (self.irq_pending.get() && self.irq_enabled.get()) || self.pcm_irq.get()
+    }
+
+    fn cpu_clock(&self, cycle: u32) {
+        for _ in 0..cycle {
+            self.apu_parity.set(!self.apu_parity.get());
+            if self.apu_parity.get() {
+                self.pulse.iter().for_each(Pulse::clock_timer);
+            }
+            let frame_cycle = self.frame_cycle.get() + 1;
+            if frame_cycle >= FRAME_PERIOD {
+                self.frame_cycle.set(0);
+                for pulse in self.pulse.iter() {
+                    pulse.clock_envelope();
+                    pulse.clock_length();
+                }
+            } else {
+                self.frame_cycle.set(frame_cycle);
+            }
+        }
+    }
+
+    fn scanline(&self, line: u32) {
+        match line {
+            // 次のラインの開始
+            0..=238 if line + 1 == u32::from(self.irq_compare.get()) => {
+                self.irq_pending.set(true);
+            }
+            239 => {
+                self.in_frame.set(false);
+                self.fetch_bank.set(None);
+                self.fetch_tile.set(None);
+                self.fetch_attr.set(None);
+            }
+            // プリレンダーライン
+            261 => {
+                self.in_frame.set(true);
+            }
+            _ => (),
+        }
+    }
+
+    fn audio(&self) -> f32 {
+        let pulse = pulse_level(self.pulse[0].output() + self.pulse[1].output());
+        pulse + f32::from(self.pcm.get()) / 255.0 * 0.25
+    }
+
+    fn prg_ram(&self) -> Option<&BankMemory> {
+        Some(&self.prg_ram)
+    }
+
+    fn chr(&self) -> Option<&BankMemory> {
+        Some(&self.chr)
+    }
+
+    fn state(&self, io: &mut StateIo) {
+        io.ref_cell(&self.exram);
+        io.cell(&self.prg_mode);
+        io.cells(&self.prg_banks);
+        io.cells(&self.prg_ram_protect);
+        io.cell(&self.chr_mode);
+        io.cells(&self.chr_a);
+        io.cells(&self.chr_b);
+        io.cell(&self.chr_upper);
+        io.cell(&self.chr_b_last);
+        io.cell(&self.sprite16);
+        io.cell(&self.exram_mode);
+        io.cell(&self.nametable);
+        io.cell(&self.fill_tile);
+        io.cell(&self.fill_attr);
+        io.cell(&self.split_control);
+        io.cell(&self.split_scroll);
+        io.cell(&self.split_bank);
+        io.cell(&self.fetch_bank);
+        io.cell(&self.fetch_tile);
+        io.cell(&self.fetch_attr);
+        io.cell(&self.irq_compare);
+        io.cell(&self.irq_enabled);
+        io.cell(&self.irq_pending);
+        io.cell(&self.in_frame);
+        io.cell(&self.multiplicand);
+        io.cell(&self.multiplier);
+        self.pulse.iter().for_each(|pulse| pulse.state(io));
+        io.cell(&self.apu_parity);
+        io.cell(&self.frame_cycle);
+        io.cell(&self.pcm);
+        io.cell(&self.pcm_control);
+        io.cell(&self.pcm_irq);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arch::mapper::mmc5::MMC5;
+    use crate::arch::mapper::tests::rom;
+    use crate::arch::mapper::Mapper;
+
+    fn mmc5() -> MMC5 {
+        MMC5::new(rom(5, 0, (0x2000, 16), (0x2000, 1)))
+    }
+
+    #[test]
+    fn is_prg_mode() {
+        let mapper = mmc5();
+        assert_eq!(mapper.cpu_read(0xE000), 15);
+        mapper.cpu_write(0x5114, 0x83);
+        assert_eq!(mapper.cpu_read(0x8000), 3);
+        // 16KB
+        mapper.cpu_write(0x5100, 0x01);
+        mapper.cpu_write(0x5115, 0x85);
+        assert_eq!(mapper.cpu_read(0x8000), 4);
+        assert_eq!(mapper.cpu_read(0xA000), 5);
+        assert_eq!(mapper.cpu_read(0xC000), 14);
+    }
+
+    #[test]
+    fn is_multiplier() {
+        let mapper = mmc5();
+        mapper.cpu_write(0x5205, 0xC8);
+        mapper.cpu_write(0x5206, 0x0F);
+        assert_eq!(mapper.cpu_read(0x5205), 0xB8);
+        assert_eq!(mapper.cpu_read(0x5206), 0x0B);
+    }
+
+    #[test]
+    fn is_scanline_irq() {
+        let mapper = mmc5();
+        mapper.cpu_write(0x5203, 0x02);
+        mapper.cpu_write(0x5204, 0x80);
+        mapper.scanline(261);
+        mapper.scanline(0);
+        assert!(!mapper.irq());
+        mapper.scanline(1);
+        assert!(mapper.irq());
+        assert_eq!(mapper.cpu_read(0x5204), 0xC0);
+        assert!(!mapper.irq());
+    }
+
+    #[test]
+    fn is_exram_attribute_on_fetch() {
+        let mapper = MMC5::new(rom(5, 0, (0x2000, 16), (0x1000, 8)));
+        mapper.scanline(261);
+        mapper.cpu_write(0x5104, 0x01);
+        mapper.cpu_write(0x5C05, 0xC3);
+        // $2007等の読み出しでは拡張属性は変わらない
+        assert_eq!(mapper.nametable_read(0x2005), None);
+        assert_eq!(mapper.nametable_read(0x23C0), None);
+        mapper.ppu_fetch(0x2005);
+        assert_eq!(mapper.nametable_read(0x23C0), Some(0xFF));
+        assert_eq!(mapper.ppu_read(0x0000), 3);
+    }
+}
@@ -0,0 +1,340 @@
+pub mod axrom;
+pub mod bandai;
+pub mod bnrom;
+pub mod camerica;
+pub mod cnrom;
+pub mod color_dreams;
+pub mod eeprom;
+pub mod fme7;
+pub mod gxrom;
+pub mod mmc1;
+pub mod mmc2;
+pub mod mmc3;
+pub mod mmc5;
+pub mod namco163;
+pub mod nina003;
+pub mod nrom;
+pub mod opll;
+pub mod uxrom;
+pub mod vrc4;
+pub mod vrc6;
+pub mod vrc7;
+pub mod vrc_irq;
+
+use log::warn;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use crate::arch::ppu::Mirroring;
+use crate::arch::snapshot::StateIo;
+use crate::parser::INes;
+use axrom::AxROM;
+use bandai::BandaiFCG;
+use bnrom::BNROM;
+use camerica::Camerica;
+use cnrom::CNROM;
+use color_dreams::ColorDreams;
+use fme7::FME7;
+use gxrom::GxROM;
+use mmc1::MMC1;
+use mmc2::{Chip, MMC2};
+use mmc3::MMC3;
+use mmc5::MMC5;
+use namco163::Namco163;
+use nina003::NINA003;
+use nrom::NROM;
+use uxrom::UxROM;
+use vrc4::VRC4;
+use vrc6::VRC6;
+use vrc7::VRC7;
+
+/// CPUMemoryとPPUで共有するカートリッジ
+pub(crate) type Cartridge = Rc<dyn Mapper>;
+
+/// カートリッジ上のマッパー
+/// CPU: $4020-$FFFF, PPU: $0000-$1FFF
+pub(crate) trait Mapper {
+    fn cpu_read(&self, addr: usize) -> u8;
+    fn cpu_write(&self, addr: usize, value: u8);
+    fn ppu_read(&self, addr: usize) -> u8;
+    fn ppu_write(&self, addr: usize, value: u8);
+
+    /// ネームテーブルのミラーリング
+    /// 実行中に切り替わる場合がある
+    fn mirroring(&self) -> Mirroring;
+
+    /// IRQ出力
+    fn irq(&self) -> bool {
+        false
+    }
+
+    /// CPUサイクル通知
+    fn cpu_clock(&self, _cycle: u32) {}
+
+    /// 描画中のネームテーブル, パターン取得通知
+    /// $2007やデバッグ表示による読み出しでは呼ばれない
+    fn ppu_fetch(&self, _addr: usize) {}
+
+    /// ネームテーブル読み出しの横取り ($2000-$2FFF)
+    /// Noneならミラーリングに従いVRAMを読む
+    fn nametable_read(&self, _addr: usize) -> Option<u8> {
+        None
+    }
+
+    /// ネームテーブル書き込みの横取り
+    /// trueならVRAMに書き込まない
+    fn nametable_write(&self, _addr: usize, _value: u8) -> bool {
+        false
+    }
+
+    /// PPUレジスタ書き込みの監視 ($2000-$2007)
+    fn ppu_register_write(&self, _addr: usize, _value: u8) {}
+
+    /// 拡張音源の出力 (-1.0-1.0)
+    fn audio(&self) -> f32 {
+        0.0
+    }
+
+    /// スキャンライン通知
+    /// 描画有効時のみ
+    fn scanline(&self, _line: u32) {}
+
+    /// $6000-$7FFFのPRG-RAM
+    fn prg_ram(&self) -> Option<&BankMemory> {
+        None
+    }
+
+    /// パターンテーブルのCHR
+    fn chr(&self) -> Option<&BankMemory> {
+        None
+    }
+
+    /// PRG-RAM, CHR-RAM以外のステートセーブ対象
+    fn state(&self, _io: &mut StateIo) {}
+
+    /// バッテリーバックアップされた内容 (.sav)
+    /// 既定ではヘッダのバッテリーフラグが立っているPRG-RAM
+    fn battery(&self) -> Option<Vec<u8>> {
+        self.prg_ram()
+            .filter(|ram| ram.battery && ram.len() > 0)
+            .map(BankMemory::dump)
+    }
+
+    /// .savの内容を復元
+    fn load_battery(&self, data: &[u8]) {
+        if let Some(ram) = self.prg_ram() {
+            ram.load(data);
+        }
+    }
+}
+
+/// 何も応答しない読み出し
+/// 直前にバスに乗ったアドレス上位バイトが残る
+pub(crate) fn open_bus(addr: usize) -> u8 {
+    (addr >> 8) as u8
+}
+
+/// iNESヘッダのマッパー番号から生成
+pub(crate) fn new(rom: INes) -> Result<Cartridge, String> {
+    let cartridge: Cartridge = match rom.mapper() {
+        0 => Rc::new(NROM::new(rom)),
+        1 => Rc::new(MMC1::new(rom)),
+        2 => Rc::new(UxROM::new(rom)),
+        3 => Rc::new(CNROM::new(rom)),
+        4 => Rc::new(MMC3::new(rom)),
+        5 => Rc::new(MMC5::new(rom)),
+        7 => Rc::new(AxROM::new(rom)),
+        9 => Rc::new(MMC2::new(rom, Chip::MMC2)),
+        10 => Rc::new(MMC2::new(rom, Chip::MMC4)),
+        11 => Rc::new(ColorDreams::new(rom)),
+        16 | 153 | 159 => Rc::new(BandaiFCG::new(rom)),
+        19 => Rc::new(Namco163::new(rom)),
+        21 | 22 | 23 | 25 => Rc::new(VRC4::new(rom)),
+        24 | 26 => Rc::new(VRC6::new(rom)),
+        34 => Rc::new(BNROM::new(rom)),
+        66 => Rc::new(GxROM::new(rom)),
+        69 => Rc::new(FME7::new(rom)),
+        71 => Rc::new(Camerica::new(rom)),
+        79 => Rc::new(NINA003::new(rom)),
+        85 => Rc::new(VRC7::new(rom)),
+        mapper => return Err(format!("unsupported mapper {}", mapper)),
+    };
+    Ok(cartridge)
+}
+
+/// トレーナーをPRG-RAMの$7000に配置
+/// .savで上書きされないよう復元の後に呼ぶ
+pub(crate) fn load_trainer(cartridge: &Cartridge, trainer: &[u8]) {
+    match cartridge.prg_ram() {
+        Some(ram) if ram.len() > 0 => {
+            for (idx, value) in trainer.iter().enumerate() {
+                ram.write(0x2000, 0, 0x1000 + idx, *value);
+            }
+        }
+        _ => warn!("trainer: no PRG-RAM"),
+    }
+}
+
+/// バンク切り替えされるROM/RAM
+pub(crate) struct BankMemory {
+    data: RefCell<Vec<u8>>,
+    writable: bool,
+    /// .savに保存する
+    battery: bool,
+    /// バンクを切り替えた
+    switched: Cell<bool>,
+}
+
+impl BankMemory {
+    pub(crate) fn rom(data: Vec<u8>) -> BankMemory {
+        BankMemory {
+            data: RefCell::new(data),
+            writable: false,
+            battery: false,
+            switched: Cell::new(false),
+        }
+    }
+
+    pub(crate) fn ram(size: usize) -> BankMemory {
+        BankMemory {
+            data: RefCell::new(vec![0x00; size]),
+            writable: true,
+            battery: false,
+            switched: Cell::new(false),
+        }
+    }
+
+    /// ヘッダのサイズ, バッテリーフラグに従うPRG-RAM
+    pub(crate) fn prg_ram(rom: &INes) -> BankMemory {
+        BankMemory {
+            battery: rom.has_battery(),
+            ..BankMemory::ram(rom.prg_ram_size())
+        }
+    }
+
+    /// CHR-ROMがなければCHR-RAM 8KB
+    pub(crate) fn chr(data: Vec<u8>) -> BankMemory {
+        if data.is_empty() {
+            BankMemory::ram(0x2000)
+        } else {
+            BankMemory::rom(data)
+        }
+    }
+
+    /// バンクレジスタの書き込み
+    /// 値が変われば切り替えとして記録する
+    pub(crate) fn switch<T: Copy + PartialEq>(&self, register: &Cell<T>, value: T) {
+        if register.replace(value) != value {
+            self.set_switched();
+        }
+    }
+
+    pub(crate) fn set_switched(&self) {
+        self.switched.set(true);
+    }
+
+    /// RAMのみ保存する
+    pub(crate) fn state(&self, io: &mut StateIo) {
+        if self.writable {
+            io.ref_cell(&self.data);
+        }
+    }
+
+    /// 前回の呼び出し以降に切り替えたか
+    pub(crate) fn take_switched(&self) -> bool {
+        self.switched.replace(false)
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.data.borrow().len()
+    }
+
+    pub(crate) fn dump(&self) -> Vec<u8> {
+        self.data.borrow().clone()
+    }
+
+    /// 先頭から上書き, 余りは捨てる
+    pub(crate) fn load(&self, data: &[u8]) {
+        let mut memory = self.data.borrow_mut();
+        let len = memory.len().min(data.len());
+        memory[..len].copy_from_slice(&data[..len]);
+    }
+
+    /// 範囲外のバンクはミラー
+    fn index(&self, bank_size: usize, bank: usize, offset: usize) -> Option<usize> {
+        match self.len() {
+            0 => None,
+            len => Some((bank * bank_size + offset % bank_size) % len),
+        }
+    }
+
+    pub(crate) fn read(&self, bank_size: usize, bank: usize, offset: usize) -> u8 {
+        self.index(bank_size, bank, offset)
+            .map(|idx| self.data.borrow()[idx])
+            .unwrap_or(0x00)
+    }
+
+    pub(crate) fn write(&self, bank_size: usize, bank: usize, offset: usize, value: u8) {
+        if !self.writable {
+            return;
+        }
+        if let Some(idx) = self.index(bank_size, bank, offset) {
+            self.data.borrow_mut()[idx] = value;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arch::mapper;
+    use crate::parser::INes;
+
+    /// テスト用ROM
+    /// PRG, CHRは(バンクサイズ, バンク数), 各バンクをバンク番号で埋める
+    /// submapperが0以外ならNES 2.0 (PRG-RAM 8KB)
+    pub(crate) fn rom(
+        mapper: u16,
+        submapper: u8,
+        prg: (usize, usize),
+        chr: (usize, usize),
+    ) -> INes {
+        let banks = |(size, count): (usize, usize)| {
+            (0..count)
+                .flat_map(|bank| vec![bank as u8; size])
+                .collect::<Vec<u8>>()
+        };
+        let mut header = [0x00; 16];
+        header[6] = ((mapper & 0x0F) << 4) as u8;
+        header[7] = (mapper & 0xF0) as u8;
+        if submapper != 0 {
+            header[7] |= 0x08;
+            header[8] = submapper << 4;
+            header[10] = 0x07;
+        }
+        INes {
+            header,
+            trainer: vec![],
+            prg: banks(prg),
+            chr: banks(chr),
+        }
+    }
+
+    #[test]
+    fn is_trainer_loaded() {
+        let mut header = [0x00; 16];
+        header[6] = 0x06;
+        let cartridge = mapper::new(INes {
+            header,
+            trainer: vec![0xEA; 0x200],
+            prg: vec![0x00; 0x4000],
+            chr: vec![0x00; 0x2000],
+        })
+        .unwrap();
+        cartridge.load_battery(&[0x11; 0x2000]);
+        mapper::load_trainer(&cartridge, &[0xEA; 0x200]);
+        assert_eq!(cartridge.cpu_read(0x6FFF), 0x11);
+        assert_eq!(cartridge.cpu_read(0x7000), 0xEA);
+        assert_eq!(cartridge.cpu_read(0x71FF), 0xEA);
+        assert_eq!(cartridge.cpu_read(0x7200), 0x11);
+    }
+}
@@ -0,0 +1,417 @@
+use std::cell::{Cell, RefCell};
+
+use crate::arch::mapper::{open_bus, BankMemory, Mapper};
+use crate::arch::ppu::Mirroring;
+use crate::arch::snapshot::StateIo;
+use crate::parser::INes;
+
+/// 1チャンネル分を更新するCPUサイクル数
+const CHANNEL_CYCLE: u32 = 15;
+/// 出力の最大振幅
+const AUDIO_LEVEL: f32 = 0.3;
+
+/// 内蔵RAM上の波形音源
+/// $40-$7Fにチャンネル0-7のレジスタ (8Byteずつ)
+/// - +0, +2, +4(1-0) 周波数 (18bit)
+/// - +1, +3, +5 位相 (24bit)
+/// - +4(7-2) 波形長 (256 - 4n)
+/// - +6 波形アドレス (4bitサンプル単位)
+/// - +7 音量, $7Fの6-4は有効チャンネル数-1
+///
+/// 15サイクルごとに1チャンネルずつ順に更新する
+struct Wavetable {
+    ram: RefCell<Vec<u8>>,
+    cycle: Cell<u32>,
+    /// 次に更新するチャンネル
+    channel: Cell<usize>,
+    outputs: [Cell<i16>; 8],
+}
+
+impl Wavetable {
+    fn new() -> Wavetable {
+        Wavetable {
+            ram: RefCell::new(vec![0x00; 0x80]),
+            cycle: Cell::new(0),
+            channel: Cell::new(7),
+            outputs: Default::default(),
+        }
+    }
+
+    /// 有効チャンネル数 (7から下に数える)
+    fn channels(&self) -> usize {
+        usize::from((self.ram.borrow()[0x7F] >> 4) & 0x07) + 1
+    }
+
+    fn register(&self, channel: usize, idx: usize) -> u32 {
+        u32::from(self.ram.borrow()[0x40 + channel * 8 + idx])
+    }
+
+    /// 4bitサンプル, 下位ニブルが先
+    fn sample(&self, idx: u32) -> i16 {
+        let byte = self.ram.borrow()[(idx as usize & 0xFF) >> 1];
+        i16::from((byte >> ((idx & 0x01) * 4)) & 0x0F)
+    }
+
+    fn update(&self, channel: usize) {
+        let frequency = self.register(channel, 0)
+            | self.register(channel, 2) << 8
+            | (self.register(channel, 4) & 0x03) << 16;
+        let phase = self.register(channel, 1)
+            | self.register(channel, 3) << 8
+            | self.register(channel, 5) << 16;
+        let length = 256 - (self.register(channel, 4) & 0xFC);
+        let phase = (phase + frequency) % (length << 16);
+        let base = 0x40 + channel * 8;
+        {
+            let mut ram = self.ram.borrow_mut();
+            ram[base + 1] = phase as u8;
+            ram[base + 3] = (phase >> 8) as u8;
+            ram[base + 5] = (phase >> 16) as u8;
+        }
+
+        let sample = self.sample((phase >> 16) + self.register(channel, 6));
+        let volume = (self.register(channel, 7) & 0x0F) as i16;
+        self.outputs[channel].set((sample - 8) * volume);
+    }
+
+    fn clock(&self, cycle: u32) {
+        let cycle = self.cycle.get() + cycle;
+        self.cycle.set(cycle % CHANNEL_CYCLE);
+        for _ in 0..cycle / CHANNEL_CYCLE {
+            let first = 8 - self.channels();
+            let channel = self.channel.get().max(first);
+            self.update(channel);
+            self.channel.set(if channel == 0 || channel == first {
+                7
+            } else {
+                channel - 1
+            });
+        }
+    }
+
+    /// 時分割出力を有効チャンネルの平均として扱う
+    fn output(&self) -> f32 {
+        let channels = self.channels();
+        let sum: i16 = self.outputs[8 - channels..].iter().map(Cell::get).sum();
+        f32::from(sum) / (channels as f32 * 120.0)
+    }
+
+    fn state(&self, io: &mut StateIo) {
+        io.ref_cell(&self.ram);
+        io.cell(&self.cycle);
+        io.cell(&self.channel);
+        io.cells(&self.outputs);
+    }
+}
+
+/// Mapper 19
+/// - $4800 内蔵RAMデータ
+/// - $5000, $5800 IRQカウンタ下位, 7: 有効 + 上位 (書き込みでacknowledge)
+/// - $8000-$BFFF CHR 1KB x8 ($E0以上はCIRAM)
+/// - $C000-$DFFF ネームテーブル 1KB x4 ($E0以上はCIRAM, 未満はCHR-ROM)
+/// - $E000 6: 音源無効, 5-0: PRG 8KB ($8000)
+/// - $E800 7, 6: $1000, $0000のCIRAM無効, 5-0: PRG 8KB ($A000)
+/// - $F000 PRG 8KB ($C000), $E000-$FFFF 最終バンク固定
+/// - $F800 7: 自動インクリメント, 6-0: 内蔵RAMアドレス
+///   上位4bitが$4のとき下位4bitが2KBごとのPRG-RAM書き込み禁止
+///
+/// IRQカウンタはCPUサイクルごとに加算し, $7FFFでIRQ
+pub(crate) struct Namco163 {
+    prg: BankMemory,
+    chr: BankMemory,
+    prg_ram: BankMemory,
+    /// ネームテーブル, CHRとしても使うためマッパー側で持つ
+    ciram: BankMemory,
+    /// CHR 8 + ネームテーブル 4
+    chr_banks: [Cell<u8>; 12],
+    prg_banks: [Cell<u8>; 3],
+    address: Cell<u8>,
+    write_protect: Cell<u8>,
+    irq_counter: Cell<u16>,
+    irq_enabled: Cell<bool>,
+    irq_pending: Cell<bool>,
+    audio: Wavetable,
+}
+
+impl Namco163 {
+    pub(crate) fn new(rom: INes) -> Namco163 {
+        let prg_ram = BankMemory::prg_ram(&rom);
+        let chr = BankMemory::chr(rom.chr);
+        Namco163 {
+            prg: BankMemory::rom(rom.prg),
+            chr,
+            prg_ram,
+            ciram: BankMemory::ram(0x800),
+            chr_banks: Default::default(),
+            prg_banks: Default::default(),
+            address: Cell::new(0x00),
+            write_protect: Cell::new(0x00),
+            irq_counter: Cell::new(0x0000),
+            irq_enabled: Cell::new(false),
+            irq_pending: Cell::new(false),
+            audio: Wavetable::new(),
+        }
+    }
+
+    /// 8KB単位
+    fn prg_bank(&self, addr: usize) -> usize {
+        match addr {
+            0xE000..=0xFFFF => (self.prg.len() / 0x2000).max(1) - 1,
+            _ => usize::from(self.prg_banks[(addr - 0x8000) >> 13].get() & 0x3F),
+        }
+    }
+
+    /// $4800の読み書きでアドレスを進める
+    fn ram_address(&self) -> usize {
+        let address = self.address.get();
+        if address & 0x80 != 0 {
+            self.address.set(0x80 | (address.wrapping_add(1) & 0x7F));
+        }
+        usize::from(address & 0x7F)
+    }
+
+    fn prg_ram_writable(&self, addr: usize) -> bool {
+        let protect = self.write_protect.get();
+        protect & 0xF0 == 0x40 && protect & (0x01 << ((addr - 0x6000) >> 11)) == 0
+    }
+
+    fn sound_enabled(&self) -> bool {
+        self.prg_banks[0].get() & 0x40 == 0
+    }
+
+    /// 1KBスロットのバンク, CIRAMならSome(ページ)
+    fn slot(&self, slot: usize) -> (usize, Option<usize>) {
+        let bank = self.chr_banks[slot].get();
+        let ciram_disabled = match slot {
+            0..=3 => self.prg_banks[1].get() & 0x40 != 0,
+            4..=7 => self.prg_banks[1].get() & 0x80 != 0,
+            _ => false,
+        };
+        if bank >= 0xE0 && !ciram_disabled {
+            (0, Some(usize::from(bank & 0x01)))
+        } else {
+            (usize::from(bank), None)
+        }
+    }
+}
+
+impl Mapper for Namco163 {
+    fn cpu_read(&self, addr: usize) -> u8 {
+        match addr {
+            0x4800..=0x4FFF => self.audio.ram.borrow()[self.ram_address()],
+            0x5000..=0x57FF => self.irq_counter.get() as u8,
+            0x5800..=0x5FFF => {
+                (self.irq_counter.get() >> 8) as u8 | (self.irq_enabled.get() as u8) << 7
+            }
+            0x4020..=0x5FFF => open_bus(addr),
+            0x6000..=0x7FFF => self.prg_ram.read(0x2000, 0, addr - 0x6000),
+            _ => self.prg.read(0x2000, self.prg_bank(addr), addr & 0x1FFF),
+        }
+    }
+
+    fn cpu_write(&self, addr: usize, value: u8) {
+        match addr {
+            0x4800..=0x4FFF => self.audio.ram.borrow_mut()[self.ram_address()] = value,
+            0x5000..=0x57FF => {
+                self.irq_counter
+                    .set((self.irq_counter.get() & 0x7F00) | u16::from(value));
+                self.irq_pending.set(false);
+            }
+            0x5800..=0x5FFF => {
+                self.irq_counter
+                    .set((self.irq_counter.get() & 0x00FF) | u16::from(value & 0x7F) << 8);
+                self.irq_enabled.set(value & 0x80 != 0);
+                self.irq_pending.set(false);
+            }
+            0x6000..=0x7FFF if self.prg_ram_writable(addr) => {
+                self.prg_ram.write(0x2000, 0, addr - 0x6000, value)
+            }
+            0x8000..=0xDFFF => self
+                .chr
+                .switch(&self.chr_banks[(addr - 0x8000) >> 11], value),
+            0xE000..=0xF7FF => self.prg_banks[(addr - 0xE000) >> 11].set(value),
+            0xF800..=0xFFFF => {
+                self.address.set(value);
+                self.write_protect.set(value);
+            }
+            _ => (),
+        }
+    }
+
+    fn ppu_read(&self, addr: usize) -> u8 {
+        match self.slot(addr >> 10) {
+            (_, Some(page)) => self.ciram.read(0x0400, page, addr & 0x03FF),
+            (bank, None) => self.chr.read(0x0400, bank, addr & 0x03FF),
+        }
+    }
+
+    fn ppu_write(&self, addr: usize, value: u8) {
+        match self.slot(addr >> 10) {
+            (_, Some(page)) => self.ciram.write(0x0400, page, addr & 0x03FF, value),
+            (bank, None) => self.chr.write(0x0400, bank, addr & 0x03FF, value),
+        }
+    }
+
+    /// CIRAMはマッパー側で持つので表示用
+    fn mirroring(&self) -> Mirroring {
+        let page = |table: usize| usize::from(self.chr_banks[8 + table].get() & 0x01);
+        Mirroring::Quadrant([page(0), page(1), page(2), page(3)])
+    }
+
+    fn nametable_read(&self, addr: usize) -> Option<u8> {
+        Some(match self.slot(8 + ((addr >> 10) & 0x03)) {
+            (_, Some(page)) => self.ciram.read(0x0400, page, addr & 0x03FF),
+            (bank, None) => self.chr.read(0x0400, bank, addr & 0x03FF),
+        })
+    }
+
+    /// CHR-ROMのページへの書き込みは捨てる
+    fn nametable_write(&self, addr: usize, value: u8) -> bool {
+        if let (_, Some(page)) = self.slot(8 + ((addr >> 10) & 0x03)) {
+            self.ciram.write(0x0400, page, addr & 0x03FF, value);
+        }
+        true
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending.get()
+    }
+
+    fn prg_ram(&self) -> Option<&BankMemory> {
+        Some(&self.prg_ram)
+    }
+
+    fn chr(&self) -> Option<&BankMemory> {
+        Some(&self.chr)
+    }
+
+    fn state(&self, io: &mut StateIo) {
+        self.ciram.state(io);
+        io.cells(&self.chr_banks);
+        io.cells(&self.prg_banks);
+        io.cell(&self.address);
+        io.cell(&self.write_protect);
+        io.cell(&self.irq_counter);
+        io.cell(&self.irq_enabled);
+        io.cell(&self.irq_pending);
+        self.audio.state(io);
+    }
+
+    fn cpu_clock(&self, cycle: u32) {
+        if self.irq_enabled.get() {
+            let counter = self.irq_counter.get();
+            if counter < 0x7FFF {
+                let counter = (u32::from(counter) + cycle).min(0x7FFF) as u16;
+                self.irq_counter.set(counter);
+                if counter == 0x7FFF {
+                    self.irq_pending.set(true);
+                }
+            }
+        }
+        if self.sound_enabled() {
+            self.audio.clock(cycle);
+        }
+    }
+
+    fn audio(&self) -> f32 {
+        if self.sound_enabled() {
+            self.audio.output() * AUDIO_LEVEL
+        } else {
+            0.0
+        }
+    }
+
+    /// PRG-RAM + 内蔵RAM
+    fn battery(&self) -> Option<Vec<u8>> {
+        if !self.prg_ram.battery {
+            return None;
+        }
+        let mut data = self.prg_ram.dump();
+        data.extend(self.audio.ram.borrow().iter());
+        Some(data)
+    }
+
+    fn load_battery(&self, data: &[u8]) {
+        let len = self.prg_ram.len().min(data.len());
+        self.prg_ram.load(&data[..len]);
+        let mut ram = self.audio.ram.borrow_mut();
+        let rest = (data.len() - len).min(ram.len());
+        ram[..rest].copy_from_slice(&data[len..len + rest]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arch::mapper::namco163::{Namco163, Wavetable};
+    use crate::arch::mapper::tests::rom;
+    use crate::arch::mapper::Mapper;
+
+    fn mapper() -> Namco163 {
+        let mut rom = rom(19, 0, (0x2000, 4), (0x400, 0x100));
+        // バッテリー
+        rom.header[6] |= 0x02;
+        Namco163::new(rom)
+    }
+
+    #[test]
+    fn is_chr_rom_nametable() {
+        let mapper = mapper();
+        // $2000: CHR-ROM, $2400: CIRAM 1
+        mapper.cpu_write(0xC000, 0x12);
+        mapper.cpu_write(0xC800, 0xE1);
+        assert_eq!(mapper.nametable_read(0x2000), Some(0x12));
+        assert!(mapper.nametable_write(0x2000, 0x55));
+        assert_eq!(mapper.nametable_read(0x2000), Some(0x12));
+        mapper.nametable_write(0x2400, 0x55);
+        assert_eq!(mapper.nametable_read(0x2400), Some(0x55));
+        // CIRAMをパターンとして読む
+        mapper.cpu_write(0x8000, 0xE1);
+        assert_eq!(mapper.ppu_read(0x0000), 0x55);
+        mapper.cpu_write(0xE800, 0x40);
+        assert_eq!(mapper.ppu_read(0x0000), 0xE1);
+    }
+
+    #[test]
+    fn is_irq_counter() {
+        let mapper = mapper();
+        mapper.cpu_write(0x5000, 0xFD);
+        mapper.cpu_write(0x5800, 0xFF);
+        mapper.cpu_clock(1);
+        assert!(!mapper.irq());
+        mapper.cpu_clock(2);
+        assert!(mapper.irq());
+        assert_eq!(mapper.cpu_read(0x5000), 0xFF);
+        mapper.cpu_write(0x5000, 0x00);
+        assert!(!mapper.irq());
+    }
+
+    #[test]
+    fn is_internal_ram_battery() {
+        let mapper = mapper();
+        mapper.cpu_write(0xF800, 0x80 | 0x7E);
+        mapper.cpu_write(0x4800, 0x11);
+        mapper.cpu_write(0x4800, 0x22);
+        mapper.cpu_write(0xF800, 0x7F);
+        assert_eq!(mapper.cpu_read(0x4800), 0x22);
+        let data = mapper.battery().unwrap();
+        assert_eq!(data.len(), 0x2000 + 0x80);
+        assert_eq!(&data[0x2000 + 0x7E..], &[0x11, 0x22]);
+    }
+
+    #[test]
+    fn is_wavetable_channels() {
+        let audio = Wavetable::new();
+        // 2チャンネル, ch6とch7を交互に更新
+        {
+            let mut ram = audio.ram.borrow_mut();
+            ram[0x7F] = 0x10 | 0x0F;
+            ram[0x00] = 0xFF;
+        }
+        audio.clock(15);
+        assert_ne!(audio.outputs[7].get(), 0);
+        assert_eq!(audio.outputs[6].get(), 0);
+        assert_eq!(audio.channel.get(), 6);
+        audio.clock(15);
+        assert_eq!(audio.channel.get(), 7);
+    }
+}
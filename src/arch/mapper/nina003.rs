@@ -0,0 +1,101 @@
+use std::cell::Cell;
+
+use crate::arch::mapper::{open_bus, BankMemory, Mapper};
+use crate::arch::ppu::Mirroring;
+use crate::arch::snapshot::StateIo;
+use crate::parser::INes;
+
+/// Mapper 79 (NINA-003/NINA-006)
+/// - $4100-$5FFF Write (A14=1, A8=1のみ)
+///   - 3 PRG 32KB 切り替え
+///   - 2-0 CHR 8KB 切り替え
+///
+/// レジスタがROM外なのでバスコンフリクトなし
+pub(crate) struct NINA003 {
+    prg: BankMemory,
+    chr: BankMemory,
+    prg_ram: BankMemory,
+    mirroring: Mirroring,
+    bank: Cell<u8>,
+}
+
+impl NINA003 {
+    pub(crate) fn new(rom: INes) -> NINA003 {
+        NINA003 {
+            prg_ram: BankMemory::prg_ram(&rom),
+            mirroring: rom.mirroring(),
+            prg: BankMemory::rom(rom.prg),
+            chr: BankMemory::chr(rom.chr),
+            bank: Cell::new(0x00),
+        }
+    }
+}
+
+impl Mapper for NINA003 {
+    fn cpu_read(&self, addr: usize) -> u8 {
+        match addr {
+            0x4020..=0x5FFF => open_bus(addr),
+            0x6000..=0x7FFF => self.prg_ram.read(0x2000, 0, addr - 0x6000),
+            _ => self.prg.read(
+                0x8000,
+                usize::from((self.bank.get() >> 3) & 0x01),
+                addr - 0x8000,
+            ),
+        }
+    }
+
+    fn cpu_write(&self, addr: usize, value: u8) {
+        match addr {
+            0x6000..=0x7FFF => self.prg_ram.write(0x2000, 0, addr - 0x6000, value),
+            _ if addr & 0xE100 == 0x4100 => self.chr.switch(&self.bank, value),
+            _ => (),
+        }
+    }
+
+    fn ppu_read(&self, addr: usize) -> u8 {
+        self.chr
+            .read(0x2000, usize::from(self.bank.get() & 0x07), addr)
+    }
+
+    fn ppu_write(&self, addr: usize, value: u8) {
+        self.chr
+            .write(0x2000, usize::from(self.bank.get() & 0x07), addr, value)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_ram(&self) -> Option<&BankMemory> {
+        Some(&self.prg_ram)
+    }
+
+    fn chr(&self) -> Option<&BankMemory> {
+        Some(&self.chr)
+    }
+
+    fn state(&self, io: &mut StateIo) {
+        io.cell(&self.bank);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arch::mapper::nina003::NINA003;
+    use crate::arch::mapper::tests::rom;
+    use crate::arch::mapper::Mapper;
+
+    #[test]
+    fn is_register_decode() {
+        let mapper = NINA003::new(rom(79, 0, (0x8000, 2), (0x2000, 8)));
+        // A8=0は無視
+        mapper.cpu_write(0x4000 + 0x20, 0x03);
+        assert_eq!(mapper.ppu_read(0x0000), 0);
+        // A15=1も無視
+        mapper.cpu_write(0xC100, 0x03);
+        assert_eq!(mapper.ppu_read(0x0000), 0);
+        mapper.cpu_write(0x5100, 0x0B);
+        assert_eq!(mapper.ppu_read(0x0000), 3);
+        assert_eq!(mapper.cpu_read(0x8000), 1);
+    }
+}
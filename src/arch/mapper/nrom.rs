@@ -0,0 +1,111 @@
+use crate::arch::mapper::{open_bus, BankMemory, Mapper};
+use crate::arch::ppu::Mirroring;
+use crate::parser::INes;
+
+/// Mapper 0
+/// PRG 16KB/32KB, CHR 8KB 切り替えなし
+/// CHR-ROMがなければCHR-RAM
+pub(crate) struct NROM {
+    prg: Vec<u8>,
+    chr: BankMemory,
+    prg_ram: BankMemory,
+    mirroring: Mirroring,
+}
+
+impl NROM {
+    pub(crate) fn new(rom: INes) -> NROM {
+        NROM {
+            prg_ram: BankMemory::prg_ram(&rom),
+            mirroring: rom.mirroring(),
+            prg: rom.prg,
+            chr: BankMemory::chr(rom.chr),
+        }
+    }
+}
+
+impl Mapper for NROM {
+    fn cpu_read(&self, addr: usize) -> u8 {
+        match addr {
+            0x4020..=0x5FFF => open_bus(addr),
+            0x6000..=0x7FFF => self.prg_ram.read(0x2000, 0, addr - 0x6000),
+            // 16KBは$C000にミラー
+            _ => self.prg[(addr - 0x8000) % self.prg.len()],
+        }
+    }
+
+    fn cpu_write(&self, addr: usize, value: u8) {
+        if let 0x6000..=0x7FFF = addr {
+            self.prg_ram.write(0x2000, 0, addr - 0x6000, value);
+        }
+    }
+
+    fn ppu_read(&self, addr: usize) -> u8 {
+        self.chr.read(0x2000, 0, addr)
+    }
+
+    fn ppu_write(&self, addr: usize, value: u8) {
+        self.chr.write(0x2000, 0, addr, value)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_ram(&self) -> Option<&BankMemory> {
+        Some(&self.prg_ram)
+    }
+
+    fn chr(&self) -> Option<&BankMemory> {
+        Some(&self.chr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arch::mapper::nrom::NROM;
+    use crate::arch::mapper::Mapper;
+    use crate::parser::INes;
+
+    #[test]
+    fn is_chr_ram() {
+        let mapper = NROM::new(INes {
+            header: [0x00; 16],
+            trainer: vec![],
+            prg: vec![0x00; 0x4000],
+            chr: vec![],
+        });
+        mapper.ppu_write(0x1FFF, 0x5A);
+        assert_eq!(mapper.ppu_read(0x1FFF), 0x5A);
+    }
+
+    #[test]
+    fn is_open_bus() {
+        let mapper = NROM::new(INes {
+            header: [0x00; 16],
+            trainer: vec![],
+            prg: vec![0x00; 0x4000],
+            chr: vec![],
+        });
+        assert_eq!(mapper.cpu_read(0x4020), 0x40);
+        assert_eq!(mapper.cpu_read(0x5FFF), 0x5F);
+    }
+
+    #[test]
+    fn is_battery_prg_ram() {
+        let mut header = [0x00; 16];
+        header[6] = 0x02;
+        let mapper = NROM::new(INes {
+            header,
+            trainer: vec![],
+            prg: vec![0x00; 0x4000],
+            chr: vec![0x00; 0x2000],
+        });
+        mapper.cpu_write(0x6001, 0x42);
+        let data = mapper.battery().unwrap();
+        assert_eq!(data.len(), 0x2000);
+        assert_eq!(data[0x0001], 0x42);
+
+        mapper.load_battery(&[0x11; 4]);
+        assert_eq!(mapper.cpu_read(0x6003), 0x11);
+    }
+}
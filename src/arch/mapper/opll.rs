@@ -0,0 +1,379 @@
+use std::cell::Cell;
+use std::f32::consts::PI;
+
+use crate::arch::snapshot::StateIo;
+
+/// OPLLのサンプリングレート (3.579545MHz / 72)
+pub(crate) const OPLL_RATE: f32 = 49_716.0;
+/// エンベロープの最大減衰(dB)
+const ATTENUATION_MAX: f32 = 48.0;
+/// 全振幅の変調器出力による搬送波の位相ずれ(周期)
+const MODULATION_DEPTH: f32 = 4.0;
+
+/// VRC7内蔵音色 1-15
+/// 0はカスタム音色($00-$07)
+const PATCHES: [[u8; 8]; 15] = [
+    [0x03, 0x21, 0x05, 0x06, 0xE8, 0x81, 0x42, 0x27],
+    [0x13, 0x41, 0x14, 0x0D, 0xD8, 0xF6, 0x23, 0x12],
+    [0x11, 0x11, 0x08, 0x08, 0xFA, 0xB2, 0x20, 0x12],
+    [0x31, 0x61, 0x0C, 0x07, 0xA8, 0x64, 0x61, 0x27],
+    [0x32, 0x21, 0x1E, 0x06, 0xE1, 0x76, 0x01, 0x28],
+    [0x02, 0x01, 0x06, 0x00, 0xA3, 0xE2, 0xF4, 0xF4],
+    [0x21, 0x61, 0x1D, 0x07, 0x82, 0x81, 0x11, 0x07],
+    [0x23, 0x21, 0x22, 0x17, 0xA2, 0x72, 0x01, 0x17],
+    [0x35, 0x11, 0x25, 0x00, 0x40, 0x73, 0x72, 0x01],
+    [0xB5, 0x01, 0x0F, 0x0F, 0xA8, 0xA5, 0x51, 0x02],
+    [0x17, 0xC1, 0x24, 0x07, 0xF8, 0xF8, 0x22, 0x12],
+    [0x71, 0x23, 0x11, 0x06, 0x65, 0x74, 0x18, 0x16],
+    [0x01, 0x02, 0xD3, 0x05, 0xC9, 0x95, 0x03, 0x02],
+    [0x61, 0x63, 0x0C, 0x00, 0x94, 0xC0, 0x33, 0xF6],
+    [0x21, 0x72, 0x0D, 0x00, 0xC1, 0xD5, 0x56, 0x06],
+];
+
+/// 周波数倍率 x2
+const MULTIPLIER: [u32; 16] = [1, 2, 4, 6, 8, 10, 12, 14, 16, 18, 20, 20, 24, 24, 30, 30];
+
+/// キースケールレベル fnum上位4bit (dB, 6dB/oct)
+const KSL_TABLE: [f32; 16] = [
+    0.0, 18.0, 24.0, 27.75, 30.0, 32.25, 33.75, 35.25, 36.0, 37.5, 38.25, 39.0, 39.75, 40.5, 41.25,
+    42.0,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Envelope {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+    Off,
+}
+
+/// オペレータ
+struct Slot {
+    /// 19bit
+    phase: Cell<u32>,
+    envelope: Cell<Envelope>,
+    /// dB
+    attenuation: Cell<f32>,
+    /// 直近2サンプル (フィードバック用)
+    output: Cell<[f32; 2]>,
+}
+
+impl Default for Slot {
+    fn default() -> Self {
+        Self {
+            phase: Cell::new(0),
+            envelope: Cell::new(Envelope::Off),
+            attenuation: Cell::new(ATTENUATION_MAX),
+            output: Cell::new([0.0; 2]),
+        }
+    }
+}
+
+impl Slot {
+    fn key_on(&self) {
+        self.phase.set(0);
+        self.envelope.set(Envelope::Attack);
+    }
+
+    fn key_off(&self) {
+        if self.envelope.get() != Envelope::Off {
+            self.envelope.set(Envelope::Release);
+        }
+    }
+
+    /// rate: 4bitのレート * 4 + キースケール
+    fn step(rate: u32) -> f32 {
+        if rate < 4 {
+            return 0.0;
+        }
+        // 48dB減衰する時間(秒)
+        let time = 10.0 / 2f32.powf(rate.min(63) as f32 / 4.0);
+        ATTENUATION_MAX / (time * OPLL_RATE)
+    }
+
+    /// 1サンプル分エンベロープを進める
+    fn clock_envelope(&self, param: &Param, release: u32, rks: u32) {
+        let rate = |value: u32| if value == 0 { 0 } else { value * 4 + rks };
+        let attenuation = self.attenuation.get();
+        let sustain = f32::from(param.sl) * 3.0;
+        let (envelope, attenuation) = match self.envelope.get() {
+            Envelope::Attack if param.ar == 15 => (Envelope::Decay, 0.0),
+            Envelope::Attack => {
+                // アタックは減衰より速い指数カーブ
+                let next = attenuation - Slot::step(rate(param.ar)) * (1.0 + attenuation) / 2.0;
+                if next <= 0.0 {
+                    (Envelope::Decay, 0.0)
+                } else {
+                    (Envelope::Attack, next)
+                }
+            }
+            Envelope::Decay => {
+                let next = attenuation + Slot::step(rate(param.dr));
+                if next >= sustain {
+                    (Envelope::Sustain, sustain)
+                } else {
+                    (Envelope::Decay, next)
+                }
+            }
+            // 持続音は鍵盤を離すまで保持
+            Envelope::Sustain if param.sustained => (Envelope::Sustain, attenuation),
+            Envelope::Sustain => (Envelope::Sustain, attenuation + Slot::step(rate(param.rr))),
+            Envelope::Release => (Envelope::Release, attenuation + Slot::step(rate(release))),
+            Envelope::Off => (Envelope::Off, ATTENUATION_MAX),
+        };
+        if attenuation >= ATTENUATION_MAX {
+            self.envelope.set(Envelope::Off);
+            self.attenuation.set(ATTENUATION_MAX);
+        } else {
+            self.envelope.set(envelope);
+            self.attenuation.set(attenuation);
+        }
+    }
+
+    /// phase_offset: 周期単位
+    fn output(&self, param: &Param, phase_offset: f32, level: f32) -> f32 {
+        if self.envelope.get() == Envelope::Off {
+            return 0.0;
+        }
+        let phase = self.phase.get() as f32 / (1 << 19) as f32 + phase_offset;
+        let wave = (2.0 * PI * phase).sin();
+        let wave = if param.half && wave < 0.0 { 0.0 } else { wave };
+        let attenuation = self.attenuation.get() + level;
+        wave * 10f32.powf(-attenuation / 20.0)
+    }
+
+    fn state(&self, io: &mut StateIo) {
+        io.cell(&self.phase);
+        io.cell(&self.envelope);
+        io.cell(&self.attenuation);
+        io.cell(&self.output);
+    }
+}
+
+/// 音色の1オペレータ分
+struct Param {
+    am: bool,
+    vib: bool,
+    sustained: bool,
+    ksr: bool,
+    mult: usize,
+    ksl: u8,
+    /// 半波整流
+    half: bool,
+    ar: u32,
+    dr: u32,
+    sl: u8,
+    rr: u32,
+}
+
+impl Param {
+    /// slot 0: 変調器, 1: 搬送波
+    fn new(patch: &[u8; 8], slot: usize) -> Param {
+        Param {
+            am: patch[slot] & 0x80 != 0,
+            vib: patch[slot] & 0x40 != 0,
+            sustained: patch[slot] & 0x20 != 0,
+            ksr: patch[slot] & 0x10 != 0,
+            mult: usize::from(patch[slot] & 0x0F),
+            ksl: patch[2 + slot] >> 6,
+            half: patch[3] & (0x08 << slot) != 0,
+            ar: u32::from(patch[4 + slot] >> 4),
+            dr: u32::from(patch[4 + slot] & 0x0F),
+            sl: patch[6 + slot] >> 4,
+            rr: u32::from(patch[6 + slot] & 0x0F),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Channel {
+    fnum: Cell<u16>,
+    block: Cell<u8>,
+    key: Cell<bool>,
+    sustain: Cell<bool>,
+    instrument: Cell<u8>,
+    volume: Cell<u8>,
+    slots: [Slot; 2],
+}
+
+impl Channel {
+    /// キースケールレベル(dB)
+    fn ksl(&self, ksl: u8) -> f32 {
+        if ksl == 0 {
+            return 0.0;
+        }
+        let level =
+            KSL_TABLE[usize::from(self.fnum.get() >> 5)] - 6.0 * f32::from(7 - self.block.get());
+        // 1.5, 3, 6 dB/oct
+        level.max(0.0) / f32::from(1u8 << (3 - ksl))
+    }
+
+    /// キースケールレート
+    fn rks(&self, ksr: bool) -> u32 {
+        let rks = u32::from(self.block.get()) << 1 | u32::from(self.fnum.get() >> 8);
+        if ksr {
+            rks
+        } else {
+            rks >> 2
+        }
+    }
+
+    fn state(&self, io: &mut StateIo) {
+        io.cell(&self.fnum);
+        io.cell(&self.block);
+        io.cell(&self.key);
+        io.cell(&self.sustain);
+        io.cell(&self.instrument);
+        io.cell(&self.volume);
+        self.slots.iter().for_each(|slot| slot.state(io));
+    }
+}
+
+/// YM2413派生のFM音源 (VRC7: 6ch, リズムなし)
+/// - $00-$07 カスタム音色
+/// - $10-$15 fnum下位
+/// - $20-$25 5: sustain, 4: key, 3-1: block, 0: fnum上位
+/// - $30-$35 7-4: 音色, 3-0: 音量
+#[derive(Default)]
+pub(crate) struct Opll {
+    address: Cell<u8>,
+    custom: [Cell<u8>; 8],
+    channels: [Channel; 6],
+    /// AM/ビブラートの位相 (サンプル数)
+    lfo: Cell<u32>,
+}
+
+impl Opll {
+    pub(crate) fn select(&self, value: u8) {
+        self.address.set(value);
+    }
+
+    pub(crate) fn write(&self, value: u8) {
+        let address = usize::from(self.address.get());
+        let channel = address & 0x0F;
+        match address {
+            0x00..=0x07 => self.custom[address].set(value),
+            0x10..=0x15 => {
+                let ch = &self.channels[channel];
+                ch.fnum.set((ch.fnum.get() & 0x100) | u16::from(value));
+            }
+            0x20..=0x25 => {
+                let ch = &self.channels[channel];
+                ch.fnum
+                    .set((ch.fnum.get() & 0xFF) | u16::from(value & 0x01) << 8);
+                ch.block.set((value >> 1) & 0x07);
+                ch.sustain.set(value & 0x20 != 0);
+                let key = value & 0x10 != 0;
+                if key && !ch.key.get() {
+                    ch.slots.iter().for_each(Slot::key_on);
+                } else if !key && ch.key.get() {
+                    ch.slots.iter().for_each(Slot::key_off);
+                }
+                ch.key.set(key);
+            }
+            0x30..=0x35 => {
+                let ch = &self.channels[channel];
+                ch.instrument.set(value >> 4);
+                ch.volume.set(value & 0x0F);
+            }
+            _ => (),
+        }
+    }
+
+    /// 全チャンネル消音
+    pub(crate) fn reset(&self) {
+        for ch in self.channels.iter() {
+            ch.key.set(false);
+            for slot in ch.slots.iter() {
+                slot.envelope.set(Envelope::Off);
+                slot.attenuation.set(ATTENUATION_MAX);
+            }
+        }
+    }
+
+    fn patch(&self, instrument: u8) -> [u8; 8] {
+        match instrument {
+            0 => {
+                let mut patch = [0x00; 8];
+                for (value, reg) in patch.iter_mut().zip(self.custom.iter()) {
+                    *value = reg.get();
+                }
+                patch
+            }
+            _ => PATCHES[usize::from(instrument) - 1],
+        }
+    }
+
+    /// 1サンプル生成
+    /// 1chの最大振幅を1.0とした合計
+    pub(crate) fn clock(&self) -> f32 {
+        // 10秒でAM, ビブラートとも整数周期
+        let lfo = (self.lfo.get() + 1) % (OPLL_RATE as u32 * 10);
+        self.lfo.set(lfo);
+        let time = lfo as f32 / OPLL_RATE;
+        // AM 3.7Hz 4.8dB, ビブラート 6.4Hz
+        let am = 2.4 * (1.0 + (2.0 * PI * 3.7 * time).sin());
+        let vib = 1.0 + 0.004 * (2.0 * PI * 6.4 * time).sin();
+
+        self.channels
+            .iter()
+            .map(|ch| self.clock_channel(ch, am, vib))
+            .sum()
+    }
+
+    fn clock_channel(&self, ch: &Channel, am: f32, vib: f32) -> f32 {
+        let patch = self.patch(ch.instrument.get());
+        let (modulator, carrier) = (Param::new(&patch, 0), Param::new(&patch, 1));
+
+        // 離鍵後のリリース: sustain指定で5, 減衰音は7, 持続音はRR
+        let release = |param: &Param| {
+            if ch.sustain.get() {
+                5
+            } else if param.sustained {
+                param.rr
+            } else {
+                7
+            }
+        };
+        for (slot, param) in ch.slots.iter().zip([&modulator, &carrier].iter()) {
+            slot.clock_envelope(param, release(param), ch.rks(param.ksr));
+            let increment =
+                (u32::from(ch.fnum.get()) * MULTIPLIER[param.mult]) << ch.block.get() >> 2;
+            let increment = if param.vib {
+                (increment as f32 * vib) as u32
+            } else {
+                increment
+            };
+            slot.phase
+                .set(slot.phase.get().wrapping_add(increment) & 0x7FFFF);
+        }
+
+        let am_level = |param: &Param| if param.am { am } else { 0.0 };
+        let [mod_slot, car_slot] = &ch.slots;
+
+        // 変調器: TL 0.75dB単位, 自己フィードバック
+        let feedback = patch[3] & 0x07;
+        let [prev0, prev1] = mod_slot.output.get();
+        let feedback = if feedback == 0 {
+            0.0
+        } else {
+            (prev0 + prev1) / 2.0 * MODULATION_DEPTH / f32::from(1u8 << (7 - feedback))
+        };
+        let level =
+            f32::from(patch[2] & 0x3F) * 0.75 + ch.ksl(modulator.ksl) + am_level(&modulator);
+        let mod_out = mod_slot.output(&modulator, feedback, level);
+        mod_slot.output.set([prev1, mod_out]);
+
+        // 搬送波: 音量 3dB単位
+        let level = f32::from(ch.volume.get()) * 3.0 + ch.ksl(carrier.ksl) + am_level(&carrier);
+        car_slot.output(&carrier, mod_out * MODULATION_DEPTH, level)
+    }
+
+    pub(crate) fn state(&self, io: &mut StateIo) {
+        io.cell(&self.address);
+        io.cells(&self.custom);
+        self.channels.iter().for_each(|channel| channel.state(io));
+        io.cell(&self.lfo);
+    }
+}
@@ -0,0 +1,101 @@
+use std::cell::Cell;
+
+use crate::arch::mapper::{open_bus, BankMemory, Mapper};
+use crate::arch::ppu::Mirroring;
+use crate::arch::snapshot::StateIo;
+use crate::parser::INes;
+
+/// Mapper 2
+/// - $8000-$BFFF 16KB 切り替え
+/// - $C000-$FFFF 最終バンク固定
+/// - CHR-RAM 8KB
+pub(crate) struct UxROM {
+    prg: BankMemory,
+    chr: BankMemory,
+    prg_ram: BankMemory,
+    mirroring: Mirroring,
+    bank: Cell<u8>,
+}
+
+impl UxROM {
+    pub(crate) fn new(rom: INes) -> UxROM {
+        let mirroring = rom.mirroring();
+        let prg_ram = BankMemory::prg_ram(&rom);
+        let chr = BankMemory::chr(rom.chr);
+        UxROM {
+            mirroring,
+            prg: BankMemory::rom(rom.prg),
+            chr,
+            prg_ram,
+            bank: Cell::new(0x00),
+        }
+    }
+
+    fn last_bank(&self) -> usize {
+        (self.prg.len() / 0x4000).max(1) - 1
+    }
+}
+
+impl Mapper for UxROM {
+    fn cpu_read(&self, addr: usize) -> u8 {
+        match addr {
+            0x4020..=0x5FFF => open_bus(addr),
+            0x6000..=0x7FFF => self.prg_ram.read(0x2000, 0, addr - 0x6000),
+            0x8000..=0xBFFF => self
+                .prg
+                .read(0x4000, usize::from(self.bank.get()), addr - 0x8000),
+            _ => self.prg.read(0x4000, self.last_bank(), addr - 0xC000),
+        }
+    }
+
+    fn cpu_write(&self, addr: usize, value: u8) {
+        match addr {
+            0x6000..=0x7FFF => self.prg_ram.write(0x2000, 0, addr - 0x6000, value),
+            0x8000..=0xFFFF => self.bank.set(value),
+            _ => (),
+        }
+    }
+
+    fn ppu_read(&self, addr: usize) -> u8 {
+        self.chr.read(0x2000, 0, addr)
+    }
+
+    fn ppu_write(&self, addr: usize, value: u8) {
+        self.chr.write(0x2000, 0, addr, value)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_ram(&self) -> Option<&BankMemory> {
+        Some(&self.prg_ram)
+    }
+
+    fn chr(&self) -> Option<&BankMemory> {
+        Some(&self.chr)
+    }
+
+    fn state(&self, io: &mut StateIo) {
+        io.cell(&self.bank);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arch::mapper::tests::rom;
+    use crate::arch::mapper::uxrom::UxROM;
+    use crate::arch::mapper::Mapper;
+
+    #[test]
+    fn is_last_bank_fixed() {
+        let mapper = UxROM::new(rom(2, 0, (0x4000, 8), (0, 0)));
+        assert_eq!(mapper.cpu_read(0x8000), 0);
+        assert_eq!(mapper.cpu_read(0xC000), 7);
+        mapper.cpu_write(0x8000, 0x03);
+        assert_eq!(mapper.cpu_read(0xBFFF), 3);
+        assert_eq!(mapper.cpu_read(0xFFFF), 7);
+        mapper.ppu_write(0x0010, 0x5A);
+        assert_eq!(mapper.ppu_read(0x0010), 0x5A);
+    }
+}
@@ -0,0 +1,285 @@
+use std::cell::Cell;
+
+use crate::arch::mapper::vrc_irq::VrcIrq;
+use crate::arch::mapper::{open_bus, BankMemory, Mapper};
+use crate::arch::ppu::Mirroring;
+use crate::arch::snapshot::StateIo;
+use crate::parser::INes;
+
+/// submapperの無いiNESダンプ向けの配線表
+/// (CRC-32, mapper, submapper)
+/// 実物のダンプで確認したものだけを載せる
+const KNOWN_CARTS: &[(u32, u16, u8)] = &[];
+
+/// ヘッダのsubmapper, 配線表の順に引く
+fn wiring(rom: &INes, carts: &[(u32, u16, u8)]) -> (u16, u8) {
+    let mapper = rom.mapper();
+    if rom.submapper() != 0 {
+        return (mapper, rom.submapper());
+    }
+    let crc = rom.crc32();
+    carts
+        .iter()
+        .find(|(cart, cart_mapper, _)| *cart == crc && *cart_mapper == mapper)
+        .map_or((mapper, 0), |(_, _, submapper)| (mapper, *submapper))
+}
+
+/// Mapper 21, 22, 23, 25
+/// 基板ごとにレジスタ選択のアドレス線が異なる
+///
+/// | mapper | submapper | chip | 下位bit, 上位bit |
+/// |--------|-----------|-------|--------|
+/// | 21 | 1 | VRC4a | A1, A2 |
+/// | 21 | 2 | VRC4c | A6, A7 |
+/// | 22 | 0 | VRC2a | A1, A0 |
+/// | 23 | 1 | VRC4f | A0, A1 |
+/// | 23 | 2 | VRC4e | A2, A3 |
+/// | 23 | 3 | VRC2b | A0, A1 |
+/// | 25 | 1 | VRC4b | A1, A0 |
+/// | 25 | 2 | VRC4d | A3, A2 |
+/// | 25 | 3 | VRC2c | A1, A0 |
+///
+/// submapperが無ければKNOWN_CARTSからPRG + CHRのCRC-32で引き,
+/// それも無ければ両方の配線を重ねて読み, VRC4として扱う
+///
+/// - $8000 PRG bank 0
+/// - $9000 mirroring, $9002 PRG swap mode (VRC4)
+/// - $A000 PRG bank 1
+/// - $B000-$E003 CHR 1KB x8 (下位4bit, 上位5bit)
+/// - $F000-$F003 IRQ (VRC4)
+pub(crate) struct VRC4 {
+    prg: BankMemory,
+    chr: BankMemory,
+    prg_ram: BankMemory,
+    /// (下位bit, 上位bit)のアドレスマスク
+    pins: Vec<(usize, usize)>,
+    vrc4: bool,
+    /// VRC2aはCHR bankの最下位bitを無視
+    chr_shift: u32,
+    prg_banks: [Cell<u8>; 2],
+    prg_swap: Cell<bool>,
+    chr_banks: [Cell<u16>; 8],
+    mirroring: Cell<Mirroring>,
+    irq: VrcIrq,
+}
+
+impl VRC4 {
+    pub(crate) fn new(rom: INes) -> VRC4 {
+        let (pins, vrc4) = match wiring(&rom, KNOWN_CARTS) {
+            (21, 1) => (vec![(0x02, 0x04)], true),
+            (21, 2) => (vec![(0x40, 0x80)], true),
+            (21, _) => (vec![(0x02, 0x04), (0x40, 0x80)], true),
+            (22, _) => (vec![(0x02, 0x01)], false),
+            (23, 1) => (vec![(0x01, 0x02)], true),
+            (23, 2) => (vec![(0x04, 0x08)], true),
+            (23, 3) => (vec![(0x01, 0x02)], false),
+            (23, _) => (vec![(0x01, 0x02), (0x04, 0x08)], true),
+            (25, 1) => (vec![(0x02, 0x01)], true),
+            (25, 2) => (vec![(0x08, 0x04)], true),
+            (25, 3) => (vec![(0x02, 0x01)], false),
+            (_, _) => (vec![(0x02, 0x01), (0x08, 0x04)], true),
+        };
+        let chr_shift = if rom.mapper() == 22 { 1 } else { 0 };
+        let mirroring = rom.mirroring();
+        let prg_ram = BankMemory::prg_ram(&rom);
+        let chr = BankMemory::chr(rom.chr);
+        VRC4 {
+            prg: BankMemory::rom(rom.prg),
+            chr,
+            prg_ram,
+            pins,
+            vrc4,
+            chr_shift,
+            prg_banks: Default::default(),
+            prg_swap: Cell::new(false),
+            chr_banks: Default::default(),
+            mirroring: Cell::new(mirroring),
+            irq: VrcIrq::default(),
+        }
+    }
+
+    /// 配線に従い$x000-$x003に正規化
+    fn register(&self, addr: usize) -> usize {
+        let reg = self.pins.iter().fold(0, |acc, (low, high)| {
+            acc | (addr & low != 0) as usize | ((addr & high != 0) as usize) << 1
+        });
+        (addr & 0xF000) | reg
+    }
+
+    /// 8KB単位
+    fn prg_bank(&self, addr: usize) -> usize {
+        let second_last = (self.prg.len() / 0x2000).max(2) - 2;
+        let bank = |idx: usize| usize::from(self.prg_banks[idx].get() & 0x1F);
+        match (addr >> 13) & 0x03 {
+            0 if self.prg_swap.get() => second_last,
+            0 => bank(0),
+            1 => bank(1),
+            2 if self.prg_swap.get() => bank(0),
+            2 => second_last,
+            _ => second_last + 1,
+        }
+    }
+
+    fn chr_bank(&self, addr: usize) -> usize {
+        usize::from(self.chr_banks[addr >> 10].get() >> self.chr_shift)
+    }
+
+    fn write_chr(&self, reg: usize, value: u8) {
+        // $B000: 0, 1 / $B002: 2, 3 ...
+        let idx = ((reg >> 12) - 0x0B) * 2 + ((reg >> 1) & 0x01);
+        let bank = self.chr_banks[idx].get();
+        let value = u16::from(value);
+        let bank = if reg & 0x01 == 0 {
+            (bank & 0x1F0) | (value & 0x0F)
+        } else {
+            (bank & 0x00F) | (value & 0x1F) << 4
+        };
+        self.chr.switch(&self.chr_banks[idx], bank);
+    }
+}
+
+impl Mapper for VRC4 {
+    fn cpu_read(&self, addr: usize) -> u8 {
+        match addr {
+            0x4020..=0x5FFF => open_bus(addr),
+            0x6000..=0x7FFF => self.prg_ram.read(0x2000, 0, addr - 0x6000),
+            _ => self.prg.read(0x2000, self.prg_bank(addr), addr & 0x1FFF),
+        }
+    }
+
+    fn cpu_write(&self, addr: usize, value: u8) {
+        if let 0x6000..=0x7FFF = addr {
+            self.prg_ram.write(0x2000, 0, addr - 0x6000, value);
+            return;
+        }
+        match self.register(addr) {
+            0x8000..=0x8003 => self.prg_banks[0].set(value),
+            0x9000..=0x9003 if !self.vrc4 => self.mirroring.set(if value & 0x01 != 0 {
+                Mirroring::Horizontal
+            } else {
+                Mirroring::Vertial
+            }),
+            0x9000 => self.mirroring.set(match value & 0x03 {
+                0 => Mirroring::Vertial,
+                1 => Mirroring::Horizontal,
+                2 => Mirroring::OneScreenLower,
+                _ => Mirroring::OneScreenUpper,
+            }),
+            0x9002 if self.vrc4 => self.prg_swap.set(value & 0x02 != 0),
+            0xA000..=0xA003 => self.prg_banks[1].set(value),
+            reg @ 0xB000..=0xEFFF => self.write_chr(reg, value),
+            0xF000 if self.vrc4 => self.irq.set_latch_low(value),
+            0xF001 if self.vrc4 => self.irq.set_latch_high(value),
+            0xF002 if self.vrc4 => self.irq.set_control(value),
+            0xF003 if self.vrc4 => self.irq.acknowledge(),
+            _ => (),
+        }
+    }
+
+    fn ppu_read(&self, addr: usize) -> u8 {
+        self.chr.read(0x0400, self.chr_bank(addr), addr & 0x03FF)
+    }
+
+    fn ppu_write(&self, addr: usize, value: u8) {
+        self.chr
+            .write(0x0400, self.chr_bank(addr), addr & 0x03FF, value)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring.get()
+    }
+
+    fn irq(&self) -> bool {
+        self.irq.is_pending()
+    }
+
+    fn cpu_clock(&self, cycle: u32) {
+        self.irq.clock(cycle);
+    }
+
+    fn prg_ram(&self) -> Option<&BankMemory> {
+        Some(&self.prg_ram)
+    }
+
+    fn chr(&self) -> Option<&BankMemory> {
+        Some(&self.chr)
+    }
+
+    fn state(&self, io: &mut StateIo) {
+        io.cells(&self.prg_banks);
+        io.cell(&self.prg_swap);
+        io.cells(&self.chr_banks);
+        io.cell(&self.mirroring);
+        self.irq.state(io);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arch::mapper::tests::rom;
+    use crate::arch::mapper::vrc4::{wiring, VRC4};
+    use crate::arch::mapper::Mapper;
+
+    fn vrc4(mapper: u16, submapper: u8) -> VRC4 {
+        VRC4::new(rom(mapper, submapper, (0x2000, 4), (0x400, 32)))
+    }
+
+    #[test]
+    fn is_register_wiring() {
+        // VRC4c: $B000 + A6 -> CHR bank 0 上位
+        let mapper = vrc4(21, 2);
+        mapper.cpu_write(0xB000, 0x05);
+        mapper.cpu_write(0xB040, 0x01);
+        assert_eq!(mapper.ppu_read(0x0000), 0x15);
+
+        // VRC4b: A1が下位bit
+        let mapper = vrc4(25, 1);
+        mapper.cpu_write(0xB000, 0x03);
+        mapper.cpu_write(0xB001, 0x07);
+        assert_eq!(mapper.ppu_read(0x0400), 0x07);
+    }
+
+    #[test]
+    fn is_known_cart_wiring() {
+        let mut check = rom(23, 0, (0, 0), (0, 0));
+        check.prg = b"12345".to_vec();
+        check.chr = b"6789".to_vec();
+        assert_eq!(check.crc32(), 0xCBF4_3926);
+
+        let known = rom(23, 0, (0x2000, 4), (0x400, 32));
+        let carts = [(known.crc32(), 23, 3)];
+        assert_eq!(wiring(&known, &carts), (23, 3));
+        // ヘッダのsubmapperを優先
+        assert_eq!(
+            wiring(&rom(23, 1, (0x2000, 4), (0x400, 32)), &carts),
+            (23, 1)
+        );
+        // 表に無ければ重ねた配線
+        assert_eq!(
+            wiring(&rom(23, 0, (0x2000, 8), (0x400, 32)), &carts),
+            (23, 0)
+        );
+    }
+
+    #[test]
+    fn is_vrc2a_chr_shift() {
+        let mapper = vrc4(22, 0);
+        mapper.cpu_write(0xB000, 0x06);
+        assert_eq!(mapper.ppu_read(0x0000), 0x03);
+    }
+
+    #[test]
+    fn is_cycle_irq() {
+        let mapper = vrc4(23, 2);
+        mapper.cpu_write(0xF000, 0x0E);
+        mapper.cpu_write(0xF004, 0x0F);
+        // CPUサイクルモード
+        mapper.cpu_write(0xF008, 0x06);
+        mapper.cpu_clock(1);
+        assert!(!mapper.irq());
+        mapper.cpu_clock(1);
+        assert!(mapper.irq());
+        mapper.cpu_write(0xF00C, 0x00);
+        assert!(!mapper.irq());
+    }
+}
@@ -0,0 +1,386 @@
+use std::cell::Cell;
+
+use crate::arch::mapper::vrc_irq::VrcIrq;
+use crate::arch::mapper::{open_bus, BankMemory, Mapper};
+use crate::arch::ppu::Mirroring;
+use crate::arch::snapshot::StateIo;
+use crate::parser::INes;
+
+/// VRC6の矩形波
+/// - 0 MDDD VVVV: デジタル出力, duty (n+1)/16, 音量
+/// - 1 周期下位
+/// - 2 E... PPPP: 有効, 周期上位
+#[derive(Default)]
+struct VrcPulse {
+    control: Cell<u8>,
+    period: Cell<u16>,
+    enabled: Cell<bool>,
+    timer: Cell<u16>,
+    step: Cell<u8>,
+}
+
+impl VrcPulse {
+    fn write(&self, reg: usize, value: u8) {
+        match reg {
+            0 => self.control.set(value),
+            1 => self
+                .period
+                .set((self.period.get() & 0x0F00) | u16::from(value)),
+            _ => {
+                self.period
+                    .set((self.period.get() & 0x00FF) | u16::from(value & 0x0F) << 8);
+                self.enabled.set(value & 0x80 != 0);
+                if !self.enabled.get() {
+                    self.step.set(15);
+                }
+            }
+        }
+    }
+
+    fn clock(&self, shift: u32) {
+        if !self.enabled.get() {
+            return;
+        }
+        if self.timer.get() == 0 {
+            self.timer.set(self.period.get() >> shift);
+            self.step.set(self.step.get().wrapping_sub(1) & 0x0F);
+        } else {
+            self.timer.set(self.timer.get() - 1);
+        }
+    }
+
+    /// 0-15
+    fn output(&self) -> u8 {
+        let control = self.control.get();
+        let duty = (control >> 4) & 0x07;
+        if self.enabled.get() && (control & 0x80 != 0 || self.step.get() <= duty) {
+            control & 0x0F
+        } else {
+            0
+        }
+    }
+
+    fn state(&self, io: &mut StateIo) {
+        io.cell(&self.control);
+        io.cell(&self.period);
+        io.cell(&self.enabled);
+        io.cell(&self.timer);
+        io.cell(&self.step);
+    }
+}
+
+/// VRC6のノコギリ波
+/// - 0 ..AA AAAA: 加算量
+/// - 1 周期下位
+/// - 2 E... PPPP: 有効, 周期上位
+///
+/// 2クロックごとに加算し, 14クロックで0に戻る
+#[derive(Default)]
+struct Sawtooth {
+    rate: Cell<u8>,
+    period: Cell<u16>,
+    enabled: Cell<bool>,
+    timer: Cell<u16>,
+    step: Cell<u8>,
+    accumulator: Cell<u8>,
+}
+
+impl Sawtooth {
+    fn write(&self, reg: usize, value: u8) {
+        match reg {
+            0 => self.rate.set(value & 0x3F),
+            1 => self
+                .period
+                .set((self.period.get() & 0x0F00) | u16::from(value)),
+            _ => {
+                self.period
+                    .set((self.period.get() & 0x00FF) | u16::from(value & 0x0F) << 8);
+                self.enabled.set(value & 0x80 != 0);
+                if !self.enabled.get() {
+                    self.step.set(0);
+                    self.accumulator.set(0);
+                }
+            }
+        }
+    }
+
+    fn clock(&self, shift: u32) {
+        if !self.enabled.get() {
+            return;
+        }
+        if self.timer.get() != 0 {
+            self.timer.set(self.timer.get() - 1);
+            return;
+        }
+        self.timer.set(self.period.get() >> shift);
+        let step = self.step.get() + 1;
+        if step >= 14 {
+            self.step.set(0);
+            self.accumulator.set(0);
+        } else {
+            self.step.set(step);
+            if step & 0x01 == 0 {
+                self.accumulator
+                    .set(self.accumulator.get().wrapping_add(self.rate.get()));
+            }
+        }
+    }
+
+    /// 0-31
+    fn output(&self) -> u8 {
+        self.accumulator.get() >> 3
+    }
+
+    fn state(&self, io: &mut StateIo) {
+        io.cell(&self.rate);
+        io.cell(&self.period);
+        io.cell(&self.enabled);
+        io.cell(&self.timer);
+        io.cell(&self.step);
+        io.cell(&self.accumulator);
+    }
+}
+
+/// Mapper 24, 26 (26はA0, A1が逆配線)
+/// - $8000 PRG 16KB ($8000)
+/// - $9000-$9002, $A000-$A002 矩形波, $B000-$B002 ノコギリ波
+/// - $9003 周波数制御 (0: 停止, 1: x16, 2: x256)
+/// - $B003 7: PRG-RAM有効, 3-2: mirroring, 1-0: CHR mode
+/// - $C000 PRG 8KB ($C000), $E000-$FFFF 最終バンク固定
+/// - $D000-$E003 CHR R0-R7
+/// - $F000-$F002 IRQ (latch, control, acknowledge)
+pub(crate) struct VRC6 {
+    prg: BankMemory,
+    chr: BankMemory,
+    prg_ram: BankMemory,
+    /// A1, A0の入れ替え
+    swapped: bool,
+    prg_banks: [Cell<u8>; 2],
+    chr_banks: [Cell<u8>; 8],
+    control: Cell<u8>,
+    irq: VrcIrq,
+    pulse: [VrcPulse; 2],
+    sawtooth: Sawtooth,
+    frequency: Cell<u8>,
+}
+
+impl VRC6 {
+    pub(crate) fn new(rom: INes) -> VRC6 {
+        let swapped = rom.mapper() == 26;
+        let prg_ram = BankMemory::prg_ram(&rom);
+        VRC6 {
+            prg: BankMemory::rom(rom.prg),
+            chr: BankMemory::chr(rom.chr),
+            prg_ram,
+            swapped,
+            prg_banks: Default::default(),
+            chr_banks: Default::default(),
+            control: Cell::new(0x00),
+            irq: VrcIrq::default(),
+            pulse: Default::default(),
+            sawtooth: Sawtooth::default(),
+            frequency: Cell::new(0x00),
+        }
+    }
+
+    /// $x000-$x003に正規化
+    fn register(&self, addr: usize) -> usize {
+        let reg = if self.swapped {
+            (addr & 0x01) << 1 | (addr & 0x02) >> 1
+        } else {
+            addr & 0x03
+        };
+        (addr & 0xF000) | reg
+    }
+
+    /// 8KB単位
+    fn prg_bank(&self, addr: usize) -> usize {
+        match addr {
+            0x8000..=0xBFFF => {
+                usize::from(self.prg_banks[0].get() & 0x0F) * 2 + ((addr >> 13) & 0x01)
+            }
+            0xC000..=0xDFFF => usize::from(self.prg_banks[1].get() & 0x1F),
+            _ => (self.prg.len() / 0x2000).max(1) - 1,
+        }
+    }
+
+    /// 1KB単位
+    /// 2KB単位のモードではA10はPPUのA10
+    fn chr_bank(&self, addr: usize) -> usize {
+        let slot = addr >> 10;
+        let bank = |idx: usize| usize::from(self.chr_banks[idx].get());
+        let wide = |idx: usize| bank(idx) & !0x01 | (slot & 0x01);
+        match (self.control.get() & 0x03, slot) {
+            (0, _) => bank(slot),
+            (1, _) => wide(slot / 2),
+            (_, 0..=3) => bank(slot),
+            (_, _) => wide(slot / 2 + 2),
+        }
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        self.control.get() & 0x80 != 0
+    }
+
+    /// 周波数制御による周期のシフト量
+    fn frequency_shift(&self) -> u32 {
+        match self.frequency.get() & 0x06 {
+            0x00 => 0,
+            0x02 => 4,
+            _ => 8,
+        }
+    }
+}
+
+impl Mapper for VRC6 {
+    fn cpu_read(&self, addr: usize) -> u8 {
+        match addr {
+            0x4020..=0x5FFF => open_bus(addr),
+            0x6000..=0x7FFF if self.prg_ram_enabled() => {
+                self.prg_ram.read(0x2000, 0, addr - 0x6000)
+            }
+            0x6000..=0x7FFF => 0x00,
+            _ => self.prg.read(0x2000, self.prg_bank(addr), addr & 0x1FFF),
+        }
+    }
+
+    fn cpu_write(&self, addr: usize, value: u8) {
+        if let 0x6000..=0x7FFF = addr {
+            if self.prg_ram_enabled() {
+                self.prg_ram.write(0x2000, 0, addr - 0x6000, value);
+            }
+            return;
+        }
+        match self.register(addr) {
+            0x8000..=0x8003 => self.prg_banks[0].set(value),
+            0x9003 => self.frequency.set(value),
+            reg @ 0x9000..=0x9002 => self.pulse[0].write(reg & 0x03, value),
+            reg @ 0xA000..=0xA002 => self.pulse[1].write(reg & 0x03, value),
+            reg @ 0xB000..=0xB002 => self.sawtooth.write(reg & 0x03, value),
+            0xB003 => self.chr.switch(&self.control, value),
+            0xC000..=0xC003 => self.prg_banks[1].set(value),
+            reg @ 0xD000..=0xE003 => {
+                let idx = ((reg >> 12) - 0x0D) * 4 + (reg & 0x03);
+                self.chr.switch(&self.chr_banks[idx], value)
+            }
+            0xF000 => self.irq.set_latch(value),
+            0xF001 => self.irq.set_control(value),
+            0xF002 => self.irq.acknowledge(),
+            _ => (),
+        }
+    }
+
+    fn ppu_read(&self, addr: usize) -> u8 {
+        self.chr.read(0x0400, self.chr_bank(addr), addr & 0x03FF)
+    }
+
+    fn ppu_write(&self, addr: usize, value: u8) {
+        self.chr
+            .write(0x0400, self.chr_bank(addr), addr & 0x03FF, value)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match (self.control.get() >> 2) & 0x03 {
+            0 => Mirroring::Vertial,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::OneScreenLower,
+            _ => Mirroring::OneScreenUpper,
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.irq.is_pending()
+    }
+
+    fn cpu_clock(&self, cycle: u32) {
+        self.irq.clock(cycle);
+        // 停止中は発振しない
+        if self.frequency.get() & 0x01 != 0 {
+            return;
+        }
+        let shift = self.frequency_shift();
+        for _ in 0..cycle {
+            self.pulse.iter().for_each(|pulse| pulse.clock(shift));
+            self.sawtooth.clock(shift);
+        }
+    }
+
+    fn audio(&self) -> f32 {
+        let sum = self.pulse[0].output() + self.pulse[1].output() + self.sawtooth.output();
+        f32::from(sum) / 61.0 * 0.4
+    }
+
+    fn prg_ram(&self) -> Option<&BankMemory> {
+        Some(&self.prg_ram)
+    }
+
+    fn chr(&self) -> Option<&BankMemory> {
+        Some(&self.chr)
+    }
+
+    fn state(&self, io: &mut StateIo) {
+        io.cells(&self.prg_banks);
+        io.cells(&self.chr_banks);
+        io.cell(&self.control);
+        self.irq.state(io);
+        self.pulse.iter().for_each(|pulse| pulse.state(io));
+        self.sawtooth.state(io);
+        io.cell(&self.frequency);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arch::mapper::tests::rom;
+    use crate::arch::mapper::vrc6::{Sawtooth, VrcPulse, VRC6};
+    use crate::arch::mapper::Mapper;
+
+    #[test]
+    fn is_swapped_wiring() {
+        let mapper = VRC6::new(rom(26, 0, (0x4000, 2), (0x400, 16)));
+        // mapper 26: $D002 -> R1
+        mapper.cpu_write(0xD002, 0x05);
+        assert_eq!(mapper.ppu_read(0x0400), 0x05);
+    }
+
+    #[test]
+    fn is_chr_ram_banked_write() {
+        let mapper = VRC6::new(rom(24, 0, (0x4000, 2), (0, 0)));
+        mapper.cpu_write(0xD001, 0x02);
+        mapper.ppu_write(0x0400, 0xAB);
+        assert_eq!(mapper.ppu_read(0x0400), 0xAB);
+        assert_eq!(mapper.ppu_read(0x0000), 0x00);
+        mapper.cpu_write(0xD000, 0x02);
+        assert_eq!(mapper.ppu_read(0x0000), 0xAB);
+    }
+
+    #[test]
+    fn is_pulse_duty() {
+        let pulse = VrcPulse::default();
+        // duty 4/16, 音量8
+        pulse.write(0, 0x38);
+        pulse.write(2, 0x80);
+        let high = (0..16)
+            .filter(|_| {
+                pulse.clock(0);
+                pulse.output() != 0
+            })
+            .count();
+        assert_eq!(high, 4);
+    }
+
+    #[test]
+    fn is_sawtooth_reset() {
+        let saw = Sawtooth::default();
+        saw.write(0, 0x08);
+        saw.write(2, 0x80);
+        let outputs: Vec<u8> = (0..14)
+            .map(|_| {
+                saw.clock(0);
+                saw.output()
+            })
+            .collect();
+        assert_eq!(outputs[11], 6);
+        assert_eq!(outputs[13], 0);
+    }
+}
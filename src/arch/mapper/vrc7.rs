@@ -0,0 +1,227 @@
+use std::cell::Cell;
+
+use crate::arch::mapper::opll::Opll;
+use crate::arch::mapper::vrc_irq::VrcIrq;
+use crate::arch::mapper::{open_bus, BankMemory, Mapper};
+use crate::arch::ppu::Mirroring;
+use crate::arch::snapshot::StateIo;
+use crate::parser::INes;
+
+/// OPLL 1サンプルのCPUサイクル数
+const OPLL_CYCLE: u32 = 36;
+/// 1chの最大振幅 (2A03矩形波の最大音量程度)
+const OPLL_LEVEL: f32 = 0.15;
+
+/// Mapper 85
+/// 第2レジスタの選択はVRC7aがA4, VRC7bがA3
+/// - $8000, $8010 PRG 8KB ($8000, $A000)
+/// - $9000 PRG 8KB ($C000), $E000-$FFFF 最終バンク固定
+/// - $9010, $9030 音源 (アドレス, データ)
+/// - $A000-$D010 CHR 1KB x8
+/// - $E000 7: PRG-RAM有効, 6: 音源リセット, 1-0: mirroring
+/// - $E010, $F000, $F010 IRQ (latch, control, acknowledge)
+pub(crate) struct VRC7 {
+    prg: BankMemory,
+    chr: BankMemory,
+    prg_ram: BankMemory,
+    prg_banks: [Cell<u8>; 3],
+    chr_banks: [Cell<u8>; 8],
+    control: Cell<u8>,
+    irq: VrcIrq,
+    opll: Opll,
+    opll_cycle: Cell<u32>,
+    output: Cell<f32>,
+}
+
+impl VRC7 {
+    pub(crate) fn new(rom: INes) -> VRC7 {
+        let prg_ram = BankMemory::prg_ram(&rom);
+        let chr = BankMemory::chr(rom.chr);
+        VRC7 {
+            prg: BankMemory::rom(rom.prg),
+            chr,
+            prg_ram,
+            prg_banks: Default::default(),
+            chr_banks: Default::default(),
+            control: Cell::new(0x00),
+            irq: VrcIrq::default(),
+            opll: Opll::default(),
+            opll_cycle: Cell::new(0),
+            output: Cell::new(0.0),
+        }
+    }
+
+    /// $x000, $x010に正規化
+    fn register(&self, addr: usize) -> usize {
+        (addr & 0xF000) | if addr & 0x18 != 0 { 0x10 } else { 0x00 }
+    }
+
+    /// 8KB単位
+    fn prg_bank(&self, addr: usize) -> usize {
+        match addr {
+            0x8000..=0xDFFF => usize::from(self.prg_banks[(addr - 0x8000) >> 13].get() & 0x3F),
+            _ => (self.prg.len() / 0x2000).max(1) - 1,
+        }
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        self.control.get() & 0x80 != 0
+    }
+}
+
+impl Mapper for VRC7 {
+    fn cpu_read(&self, addr: usize) -> u8 {
+        match addr {
+            0x4020..=0x5FFF => open_bus(addr),
+            0x6000..=0x7FFF if self.prg_ram_enabled() => {
+                self.prg_ram.read(0x2000, 0, addr - 0x6000)
+            }
+            0x6000..=0x7FFF => 0x00,
+            _ => self.prg.read(0x2000, self.prg_bank(addr), addr & 0x1FFF),
+        }
+    }
+
+    fn cpu_write(&self, addr: usize, value: u8) {
+        if let 0x6000..=0x7FFF = addr {
+            if self.prg_ram_enabled() {
+                self.prg_ram.write(0x2000, 0, addr - 0x6000, value);
+            }
+            return;
+        }
+        // 音源はA5で区別
+        match addr & 0xF030 {
+            0x9010 => return self.opll.select(value),
+            0x9030 => return self.opll.write(value),
+            _ => (),
+        }
+        match self.register(addr) {
+            0x8000 => self.prg_banks[0].set(value),
+            0x8010 => self.prg_banks[1].set(value),
+            0x9000 => self.prg_banks[2].set(value),
+            reg @ 0xA000..=0xD010 => {
+                let idx = ((reg >> 12) - 0x0A) * 2 + ((reg >> 4) & 0x01);
+                self.chr.switch(&self.chr_banks[idx], value)
+            }
+            0xE000 => {
+                if value & 0x40 != 0 {
+                    self.opll.reset();
+                }
+                self.control.set(value);
+            }
+            0xE010 => self.irq.set_latch(value),
+            0xF000 => self.irq.set_control(value),
+            0xF010 => self.irq.acknowledge(),
+            _ => (),
+        }
+    }
+
+    fn ppu_read(&self, addr: usize) -> u8 {
+        let bank = usize::from(self.chr_banks[addr >> 10].get());
+        self.chr.read(0x0400, bank, addr & 0x03FF)
+    }
+
+    fn ppu_write(&self, addr: usize, value: u8) {
+        let bank = usize::from(self.chr_banks[addr >> 10].get());
+        self.chr.write(0x0400, bank, addr & 0x03FF, value)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.control.get() & 0x03 {
+            0 => Mirroring::Vertial,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::OneScreenLower,
+            _ => Mirroring::OneScreenUpper,
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.irq.is_pending()
+    }
+
+    fn cpu_clock(&self, cycle: u32) {
+        self.irq.clock(cycle);
+        let mut total = self.opll_cycle.get() + cycle;
+        while total >= OPLL_CYCLE {
+            total -= OPLL_CYCLE;
+            self.output.set(self.opll.clock());
+        }
+        self.opll_cycle.set(total);
+    }
+
+    fn audio(&self) -> f32 {
+        // リセット中は無音
+        if self.control.get() & 0x40 != 0 {
+            return 0.0;
+        }
+        self.output.get() * OPLL_LEVEL
+    }
+
+    fn prg_ram(&self) -> Option<&BankMemory> {
+        Some(&self.prg_ram)
+    }
+
+    fn chr(&self) -> Option<&BankMemory> {
+        Some(&self.chr)
+    }
+
+    fn state(&self, io: &mut StateIo) {
+        io.cells(&self.prg_banks);
+        io.cells(&self.chr_banks);
+        io.cell(&self.control);
+        self.irq.state(io);
+        self.opll.state(io);
+        io.cell(&self.opll_cycle);
+        io.cell(&self.output);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arch::mapper::tests::rom;
+    use crate::arch::mapper::vrc7::VRC7;
+    use crate::arch::mapper::Mapper;
+
+    fn vrc7() -> VRC7 {
+        VRC7::new(rom(85, 0, (0x2000, 8), (0x2000, 1)))
+    }
+
+    #[test]
+    fn is_prg_wiring() {
+        let mapper = vrc7();
+        // VRC7a: A4, VRC7b: A3
+        mapper.cpu_write(0x8010, 0x03);
+        assert_eq!(mapper.cpu_read(0xA000), 3);
+        mapper.cpu_write(0x8008, 0x04);
+        assert_eq!(mapper.cpu_read(0xA000), 4);
+        assert_eq!(mapper.cpu_read(0xE000), 7);
+    }
+
+    #[test]
+    fn is_fm_key_on_off() {
+        let mapper = vrc7();
+        let write = |reg: u8, value: u8| {
+            mapper.cpu_write(0x9010, reg);
+            mapper.cpu_write(0x9030, value);
+        };
+        // 音色1 最大音量, A4付近
+        write(0x30, 0x10);
+        write(0x10, 0x21);
+        write(0x20, 0x19);
+        let peak = |mapper: &VRC7| {
+            (0..2000)
+                .map(|_| {
+                    mapper.cpu_clock(36);
+                    mapper.audio().abs()
+                })
+                .fold(0.0, f32::max)
+        };
+        assert!(peak(&mapper) > 0.01);
+
+        // 離鍵後は減衰
+        write(0x20, 0x09);
+        for _ in 0..50 {
+            peak(&mapper);
+        }
+        assert!(peak(&mapper) < 0.01);
+    }
+}
@@ -0,0 +1,95 @@
+use std::cell::Cell;
+
+use crate::arch::snapshot::StateIo;
+
+/// スキャンラインモードの分周 (CPU 1サイクル = PPU 3ドット)
+const PRESCALER: i32 = 341;
+
+/// コナミVRC4/6/7共通のIRQカウンタ
+/// - latch: 再読込値
+/// - control
+///   - 2 mode (0: スキャンライン, 1: CPUサイクル)
+///   - 1 enable
+///   - 0 acknowledge時のenable
+///
+/// カウンタが$FFから桁あふれするとlatchを再読込してIRQ
+#[derive(Default)]
+pub(crate) struct VrcIrq {
+    latch: Cell<u8>,
+    counter: Cell<u8>,
+    prescaler: Cell<i32>,
+    control: Cell<u8>,
+    pending: Cell<bool>,
+}
+
+impl VrcIrq {
+    pub(crate) fn set_latch(&self, value: u8) {
+        self.latch.set(value);
+    }
+
+    /// VRC4は4bitずつ書き込む
+    pub(crate) fn set_latch_low(&self, value: u8) {
+        self.latch.set((self.latch.get() & 0xF0) | (value & 0x0F));
+    }
+
+    pub(crate) fn set_latch_high(&self, value: u8) {
+        self.latch
+            .set((self.latch.get() & 0x0F) | (value & 0x0F) << 4);
+    }
+
+    pub(crate) fn set_control(&self, value: u8) {
+        self.control.set(value);
+        self.pending.set(false);
+        if value & 0x02 != 0 {
+            self.counter.set(self.latch.get());
+            self.prescaler.set(PRESCALER);
+        }
+    }
+
+    pub(crate) fn acknowledge(&self) {
+        self.pending.set(false);
+        let control = self.control.get();
+        self.control.set((control & !0x02) | (control & 0x01) << 1);
+    }
+
+    pub(crate) fn is_pending(&self) -> bool {
+        self.pending.get()
+    }
+
+    pub(crate) fn clock(&self, cycle: u32) {
+        for _ in 0..cycle {
+            if self.control.get() & 0x02 == 0 {
+                return;
+            }
+            if self.control.get() & 0x04 != 0 {
+                self.clock_counter();
+                continue;
+            }
+            let prescaler = self.prescaler.get() - 3;
+            if prescaler <= 0 {
+                self.prescaler.set(prescaler + PRESCALER);
+                self.clock_counter();
+            } else {
+                self.prescaler.set(prescaler);
+            }
+        }
+    }
+
+    fn clock_counter(&self) {
+        match self.counter.get() {
+            0xFF => {
+                self.counter.set(self.latch.get());
+                self.pending.set(true);
+            }
+            counter => self.counter.set(counter + 1),
+        }
+    }
+
+    pub(crate) fn state(&self, io: &mut StateIo) {
+        io.cell(&self.latch);
+        io.cell(&self.counter);
+        io.cell(&self.prescaler);
+        io.cell(&self.control);
+        io.cell(&self.pending);
+    }
+}
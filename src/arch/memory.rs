@@ -1,6 +1,10 @@
+use crate::arch::input::Input;
+use crate::arch::mapper::{open_bus, Cartridge};
+use crate::arch::ppu::{Frame, Mirroring};
 use crate::arch::RcRefCell;
 use std::cell::{Cell, RefCell};
 use std::ops::Not;
+use std::rc::Rc;
 
 /// WRAM: 2KByte
 /// IOP: PPU I/O
@@ -8,7 +12,7 @@ use std::ops::Not;
 /// APU, PAD
 /// ROM: 32KByte
 ///
-/// ROMも同様
+/// $4020以降はカートリッジ
 pub struct CPUMemory {
     /// 2KB WRAM
     pub(crate) wram: RefCell<[u8; 0x0800]>,
@@ -16,17 +20,24 @@ pub struct CPUMemory {
     pub(crate) iop: RcRefCell<PPURegister>,
     /// APU, PAD
     pub(crate) ioa: [u8; 0x0020],
-    /// ROMプログラム部
-    pub(crate) prg: Vec<u8>,
+    /// $4016, $4017
+    pub(crate) input: Input,
+    /// カートリッジ
+    pub(crate) cartridge: Cartridge,
 }
 
 impl CPUMemory {
-    pub(crate) fn new(rom: Vec<u8>, prg: RcRefCell<PPURegister>) -> CPUMemory {
+    pub(crate) fn new(
+        cartridge: Cartridge,
+        prg: RcRefCell<PPURegister>,
+        frame: Rc<Frame>,
+    ) -> CPUMemory {
         CPUMemory {
             wram: RefCell::new([0x00; 0x0800]),
             iop: prg,
             ioa: [0x00; 0x0020],
-            prg: rom,
+            input: Input::new(frame),
+            cartridge,
         }
     }
 
@@ -71,17 +82,17 @@ impl CPUMemory {
             unreachable!()
         // APU, PAD
         } else if addr < 0x4020usize {
-            unimplemented!("APU, PAD")
-        // Expand ROM
-        } else if addr < 0x6000usize {
-            unimplemented!("Ex ROM")
-        // Expand RAM
-        } else if addr < 0x8000usize {
-            unimplemented!("Ex RAM")
-        // ROM
+            match addr {
+                // 上位bitはオープンバス
+                0x4016 => 0x40 | self.input.read(0),
+                0x4017 => 0x40 | self.input.read(1),
+                // APUステータスは未実装
+                0x4015 => 0x00,
+                _ => open_bus(addr),
+            }
+        // Expand ROM, Expand RAM, ROM
         } else if addr < 0x10000usize {
-            let addr = addr - 0x8000usize;
-            self.prg[addr]
+            self.cartridge.cpu_read(addr)
         } else {
             unreachable!("Out of Memory")
         }
@@ -100,6 +111,7 @@ impl CPUMemory {
             ram[addr] = value;
         // PPU
         } else if addr < 0x2008usize {
+            self.cartridge.ppu_register_write(addr, value);
             let ppu_reg = &mut self.iop.borrow_mut();
             match addr {
                 0x2000 => ppu_reg.ppuctrl.set(value),
@@ -151,16 +163,27 @@ impl CPUMemory {
             unreachable!()
         // APU, PAD
         } else if addr < 0x4020usize {
-
-            // Expand ROM
+            if addr == 0x4016 {
+                self.input.write(value);
+            }
+        // Expand ROM, Expand RAM, ROM
+        } else if addr < 0x10000usize {
+            self.cartridge.cpu_write(addr, value);
         } else {
-            unimplemented!()
+            unreachable!("Out of Memory")
         }
     }
 }
 
 // VRAM E0117
-pub(crate) struct PPUMemory(pub(crate) RefCell<[u8; 0x4000]>);
+/// $0000-$1FFF: カートリッジ
+/// $2000-$2FFF: ネームテーブル 2KBをミラーリング
+pub(crate) struct PPUMemory {
+    pub(crate) vram: RefCell<[u8; 0x4000]>,
+    pub(crate) cartridge: Cartridge,
+    /// CHR-RAMへの書き込みがあった
+    pub(crate) pattern_dirty: Cell<bool>,
+}
 
 impl std::fmt::Debug for PPUMemory {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
@@ -168,21 +191,40 @@ impl std::fmt::Debug for PPUMemory {
     }
 }
 
-impl Default for PPUMemory {
-    fn default() -> Self {
-        Self(RefCell::new([0x00; 0x4000]))
+impl PPUMemory {
+    pub(crate) fn new(cartridge: Cartridge) -> Self {
+        Self {
+            vram: RefCell::new([0x00; 0x4000]),
+            cartridge,
+            pattern_dirty: Cell::new(false),
+        }
     }
-}
 
-impl PPUMemory {
-    pub(crate) fn read(&self, addr: usize) -> u8 {
-        let PPUMemory(vram) = self;
+    /// ネームテーブル -> VRAM $2000-$27FF
+    fn nametable(&self, addr: usize) -> usize {
+        let offset = addr & 0x03FF;
+        let table = ((addr - 0x2000) / 0x0400) & 0x03;
+        let page = match self.cartridge.mirroring() {
+            Mirroring::Horizontal => table / 2,
+            Mirroring::Vertial => table % 2,
+            Mirroring::OneScreenLower => 0,
+            Mirroring::OneScreenUpper => 1,
+            Mirroring::Quadrant(pages) => pages[table] & 0x01,
+        };
+        0x2000 + page * 0x0400 + offset
+    }
 
+    pub(crate) fn read(&self, addr: usize) -> u8 {
         let addr = match addr {
-            0x0000...0x27FF => addr,
+            0x0000...0x1FFF => return self.cartridge.ppu_read(addr),
             // mirror 0x2000
-            0x2800...0x2FFF => addr - 0x0800,
-            0x3000...0x3EFF => addr - 0x1000,
+            0x2000...0x3EFF => {
+                let addr = 0x2000 | (addr & 0x0FFF);
+                if let Some(value) = self.cartridge.nametable_read(addr) {
+                    return value;
+                }
+                self.nametable(addr)
+            }
             0x3F00...0x3F1F => match addr {
                 0x3F10 => 0x3F00,
                 0x3F14 => 0x3F04,
@@ -195,17 +237,23 @@ impl PPUMemory {
             _ => unreachable!(),
         };
 
-        vram.borrow()[addr]
+        self.vram.borrow()[addr]
     }
 
     pub(crate) fn write(&self, addr: usize, value: u8) {
-        let PPUMemory(vram) = self;
-
         let addr = match addr {
-            0x0000...0x27FF => addr,
+            0x0000...0x1FFF => {
+                self.pattern_dirty.set(true);
+                return self.cartridge.ppu_write(addr, value);
+            }
             // mirror 0x2000
-            0x2800...0x2FFF => addr - 0x0800,
-            0x3000...0x3EFF => addr - 0x1000,
+            0x2000...0x3EFF => {
+                let addr = 0x2000 | (addr & 0x0FFF);
+                if self.cartridge.nametable_write(addr, value) {
+                    return;
+                }
+                self.nametable(addr)
+            }
             0x3F00...0x3F1F => match addr {
                 0x3F10 => 0x3F00,
                 0x3F14 => 0x3F04,
@@ -218,7 +266,7 @@ impl PPUMemory {
             _ => unreachable!("PPU Write Address: {:X}", addr),
         };
 
-        vram.borrow_mut()[addr] = value;
+        self.vram.borrow_mut()[addr] = value;
     }
 }
 
@@ -288,8 +336,8 @@ pub(crate) struct PPURegister {
     pub ppudata: PPUMemory,
 }
 
-impl Default for PPURegister {
-    fn default() -> Self {
+impl PPURegister {
+    pub(crate) fn new(cartridge: Cartridge) -> Self {
         Self {
             ppuctrl: Cell::new(0b0100_0000),
             ppumask: Cell::new(0x00),
@@ -300,12 +348,10 @@ impl Default for PPURegister {
             ppuscroll: Cell::new(0x00),
             ppuaddr: Cell::new(0x00),
             ppuaddr_bit_flag: Cell::new(BitFlag::High),
-            ppudata: PPUMemory::default(),
+            ppudata: PPUMemory::new(cartridge),
         }
     }
-}
 
-impl PPURegister {
     pub(crate) fn set_vblank(&self) {
         let reg = self.ppustatus.get();
         self.ppustatus.set(reg | 0x80);
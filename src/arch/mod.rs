@@ -1,8 +1,18 @@
+pub mod apu;
+pub mod arkanoid;
 pub mod cpu;
+pub mod input;
+pub mod keyboard;
+pub mod mapper;
 pub mod memory;
 pub mod op;
+pub mod pad;
+pub mod power_pad;
 pub mod ppu;
+pub mod recorder;
 pub mod register;
+pub mod snapshot;
+pub mod zapper;
 
 use log::info;
 use memory::{CPUMemory, PPURegister};
@@ -11,12 +21,27 @@ use sdl2::video::Window;
 use std::cell::RefCell;
 use std::rc::Rc;
 
-use ppu::Mirroring;
+use crate::parser::INes;
+
+use apu::Mixer;
+use arkanoid::Arkanoid;
+use input::{Expansion, MultiTap, Port2};
+use keyboard::Keyboard;
+use mapper::Cartridge;
+use pad::Pad;
+use power_pad::{PowerPad, Side};
+use ppu::Frame;
+use recorder::DataRecorder;
 use register::Register;
+use snapshot::{Snapshot, StateIo};
+use zapper::Zapper;
 use {cpu::CPU, ppu::PPU};
 
 pub(crate) type RcRefCell<T> = Rc<RefCell<T>>;
 
+/// 割り込み処理のサイクル数
+const IRQ_CYCLE: u32 = 7;
+
 pub(crate) enum WriteAddr {
     Memory(usize),
     None,
@@ -39,21 +64,23 @@ pub(crate) enum Opeland {
 pub struct Arch {
     pub(crate) cpu: CPU,
     pub(crate) ppu: PPU,
+    pub(crate) cartridge: Cartridge,
+    pub(crate) mixer: Mixer,
+    trainer: Vec<u8>,
 }
 
 impl Arch {
-    pub fn new(
-        rom: Vec<u8>,
-        chr: Vec<u8>,
-        canvas: RcRefCell<Canvas<Window>>,
-        mirroring: Mirroring,
-    ) -> Arch {
+    pub fn new(mut rom: INes, canvas: RcRefCell<Canvas<Window>>) -> Result<Arch, String> {
+        info!("Cartridge init: mapper {}", rom.mapper());
+        let trainer = std::mem::take(&mut rom.trainer);
+        let cartridge = mapper::new(rom)?;
         info!("PPU Register init");
-        let ppu_reg = Rc::new(RefCell::new(PPURegister::default()));
+        let ppu_reg = Rc::new(RefCell::new(PPURegister::new(cartridge.clone())));
         info!("CPU Register init");
         let cpu_reg = Register::default();
+        let frame = Rc::new(Frame::default());
         info!("Memory init");
-        let memory = CPUMemory::new(rom, ppu_reg.clone());
+        let memory = CPUMemory::new(cartridge.clone(), ppu_reg.clone(), frame.clone());
 
         info!("CPU init");
         let cpu = CPU {
@@ -62,9 +89,15 @@ impl Arch {
         };
 
         info!("PPU init");
-        let ppu = PPU::new(chr, ppu_reg, canvas, mirroring);
+        let ppu = PPU::new(cartridge.clone(), ppu_reg, canvas, frame);
         info!("Init done");
-        Arch { cpu, ppu }
+        Ok(Arch {
+            cpu,
+            ppu,
+            cartridge,
+            mixer: Mixer::default(),
+            trainer,
+        })
     }
 
     pub fn frame(&self) {
@@ -72,12 +105,134 @@ impl Arch {
         let opecode = op::Operation::new(addr);
         // info!("{:?}", opecode);
         self.cpu.exec(&opecode);
-        self.ppu.run(3 * opecode.cycle);
+        self.clock(opecode.cycle);
+
+        // カートリッジのIRQ線
+        if self.cartridge.irq() && self.cpu.irq() {
+            self.clock(IRQ_CYCLE);
+        }
+    }
+
+    /// CPU以外をサイクル分進める
+    fn clock(&self, cycle: u32) {
+        self.cpu.memory.input.clock(cycle);
+        self.cartridge.cpu_clock(cycle);
+        self.mixer.clock(cycle, self.cartridge.audio());
+        self.ppu.run(3 * cycle);
+    }
+
+    /// 1フレーム分程度溜まった音声
+    pub fn audio_samples(&self) -> Option<Vec<i16>> {
+        self.mixer.drain()
+    }
+
+    /// バッテリーバックアップされた内容
+    pub fn battery(&self) -> Option<Vec<u8>> {
+        self.cartridge.battery()
+    }
+
+    /// CPU, RAM, PPU, カートリッジの状態をメモリ上に保存
+    pub fn save_state(&self) -> Snapshot {
+        let mut snapshot = Snapshot::default();
+        self.state(&mut StateIo::save(&mut snapshot));
+        snapshot
+    }
+
+    pub fn load_state(&self, snapshot: &Snapshot) {
+        self.state(&mut StateIo::load(snapshot));
+        if let Some(chr) = self.cartridge.chr() {
+            chr.set_switched();
+        }
+    }
+
+    fn state(&self, io: &mut StateIo) {
+        let register = &self.cpu.register;
+        io.cell(&register.a);
+        io.cell(&register.x);
+        io.cell(&register.y);
+        io.cell(&register.pc);
+        io.cell(&register.sp);
+        io.cell(&register.p);
+        io.ref_cell(&self.cpu.memory.wram);
+
+        let ppu_reg = self.ppu.ioc.borrow();
+        io.cell(&ppu_reg.ppuctrl);
+        io.cell(&ppu_reg.ppumask);
+        io.cell(&ppu_reg.ppustatus);
+        io.cell(&ppu_reg.oamaddr);
+        io.cell(&ppu_reg.oamaddr_bit_flag);
+        io.cell(&ppu_reg.oamdata);
+        io.cell(&ppu_reg.ppuscroll);
+        io.cell(&ppu_reg.ppuaddr);
+        io.cell(&ppu_reg.ppuaddr_bit_flag);
+        io.ref_cell(&ppu_reg.ppudata.vram);
+        io.ref_cell(&self.ppu.state);
+        io.cell(&self.ppu.frame.line);
+        io.cell(&self.ppu.frame.count);
+
+        if let Some(ram) = self.cartridge.prg_ram() {
+            ram.state(io);
+        }
+        if let Some(chr) = self.cartridge.chr() {
+            chr.state(io);
+        }
+        self.cartridge.state(io);
+    }
+
+    pub fn load_battery(&self, data: &[u8]) {
+        self.cartridge.load_battery(data);
+    }
+
+    /// .savの復元後に呼ぶ
+    pub fn load_trainer(&self) {
+        if !self.trainer.is_empty() {
+            mapper::load_trainer(&self.cartridge, &self.trainer);
+        }
     }
 
     pub fn reset(&self) {
         self.cpu.register.hard_reset();
     }
+
+    pub fn pad(&self, port: usize) -> &Pad {
+        &self.cpu.memory.input.pads[port]
+    }
+
+    pub fn set_multitap(&self, multitap: MultiTap) {
+        self.cpu.memory.input.multitap.set(multitap);
+    }
+
+    pub fn set_port2(&self, device: Port2) {
+        self.cpu.memory.input.port2.set(device);
+    }
+
+    pub fn set_expansion(&self, device: Expansion) {
+        self.cpu.memory.input.expansion.set(device);
+    }
+
+    pub fn zapper(&self) -> &Zapper {
+        &self.cpu.memory.input.zapper
+    }
+
+    pub fn arkanoid(&self) -> &Arkanoid {
+        &self.cpu.memory.input.arkanoid
+    }
+
+    pub fn power_pad(&self) -> &PowerPad {
+        &self.cpu.memory.input.power_pad
+    }
+
+    pub fn set_power_pad_side(&self, side: Side) {
+        self.cpu.memory.input.power_pad.side.set(side);
+    }
+
+    pub fn keyboard(&self) -> &Keyboard {
+        &self.cpu.memory.input.keyboard
+    }
+
+    pub fn recorder(&self) -> &DataRecorder {
+        &self.cpu.memory.input.recorder
+    }
 }
 
 pub trait Accumulate {
@@ -452,7 +452,12 @@ impl CPU {
                 let pc_high = u16::from(self.stack_pop()) << 8;
                 self.register.pc.set(pc_low + pc_high);
             }
-            RTI => unimplemented!(),
+            RTI => {
+                self.register.p.set(State::from_u8(self.stack_pop()));
+                let pc_low = u16::from(self.stack_pop());
+                let pc_high = u16::from(self.stack_pop()) << 8;
+                self.register.pc.set(pc_low + pc_high);
+            }
             _ => unreachable!(),
         }
     }
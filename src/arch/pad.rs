@@ -0,0 +1,178 @@
+use std::cell::Cell;
+
+/// 標準コントローラのボタン
+/// 読み出し順に並べる
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Button {
+    A,
+    B,
+    Select,
+    Start,
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Button {
+    pub const ALL: [Button; 8] = [
+        Button::A,
+        Button::B,
+        Button::Select,
+        Button::Start,
+        Button::Up,
+        Button::Down,
+        Button::Left,
+        Button::Right,
+    ];
+
+    fn bit(self) -> u8 {
+        match self {
+            Button::A => 0x01,
+            Button::B => 0x02,
+            Button::Select => 0x04,
+            Button::Start => 0x08,
+            Button::Up => 0x10,
+            Button::Down => 0x20,
+            Button::Left => 0x40,
+            Button::Right => 0x80,
+        }
+    }
+}
+
+/// 連射の既定周期(フレーム)
+/// 2フレーム押下, 2フレーム解放
+const TURBO_PERIOD: u32 = 4;
+
+/// 標準コントローラ
+/// $4016 Write bit0でボタン状態をラッチ
+/// $4016/$4017 Read bit0から1bitずつシフトアウト
+#[derive(Debug)]
+pub struct Pad {
+    /// 現在押されているボタン
+    buttons: Cell<u8>,
+    /// 連射ボタンとして押されているボタン
+    turbo: Cell<u8>,
+    /// ボタンごとの連射周期(フレーム)
+    /// 実時間ではなくエミュレータのフレーム数で同期する
+    turbo_periods: [Cell<u32>; 8],
+    /// ラッチ済みのシフトレジスタ
+    shift: Cell<u8>,
+    strobe: Cell<bool>,
+}
+
+impl Default for Pad {
+    fn default() -> Self {
+        Self {
+            buttons: Cell::new(0x00),
+            turbo: Cell::new(0x00),
+            turbo_periods: Default::default(),
+            shift: Cell::new(0x00),
+            strobe: Cell::new(false),
+        }
+    }
+}
+
+impl Pad {
+    pub fn set_button(&self, button: Button, pressed: bool) {
+        let buttons = self.buttons.get();
+        self.buttons.set(if pressed {
+            buttons | button.bit()
+        } else {
+            buttons & !button.bit()
+        });
+    }
+
+    pub fn set_turbo(&self, button: Button, pressed: bool) {
+        let turbo = self.turbo.get();
+        self.turbo.set(if pressed {
+            turbo | button.bit()
+        } else {
+            turbo & !button.bit()
+        });
+    }
+
+    /// 2フレーム未満は2フレーム
+    pub fn set_turbo_period(&self, button: Button, frames: u32) {
+        self.turbo_periods[button as usize].set(frames.max(2));
+    }
+
+    pub fn release_all(&self) {
+        self.buttons.set(0x00);
+        self.turbo.set(0x00);
+    }
+
+    /// frame: 電源投入からのフレーム数
+    pub(crate) fn buttons(&self, frame: u32) -> u8 {
+        let turbo = Button::ALL
+            .iter()
+            .filter(|button| self.turbo.get() & button.bit() != 0)
+            .filter(|button| {
+                let period = match self.turbo_periods[**button as usize].get() {
+                    0 => TURBO_PERIOD,
+                    period => period,
+                };
+                // 周期の前半は押下
+                frame % period < period / 2
+            })
+            .fold(0x00, |acc, button| acc | button.bit());
+        self.buttons.get() | turbo
+    }
+
+    pub(crate) fn write(&self, value: u8, frame: u32) {
+        let strobe = value & 0x01 != 0;
+        self.strobe.set(strobe);
+        if strobe {
+            self.shift.set(self.buttons(frame));
+        }
+    }
+
+    pub(crate) fn read(&self, frame: u32) -> u8 {
+        // strobe中はAを返し続ける
+        if self.strobe.get() {
+            return self.buttons(frame) & 0x01;
+        }
+        let shift = self.shift.get();
+        // 8bit読み切ったあとは1
+        self.shift.set((shift >> 1) | 0x80);
+        shift & 0x01
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arch::pad::{Button, Pad};
+
+    #[test]
+    fn is_shift_out_in_order() {
+        let pad = Pad::default();
+        pad.set_button(Button::A, true);
+        pad.set_button(Button::Start, true);
+        pad.write(1, 0);
+        pad.write(0, 0);
+        let bits = (0..8).map(|_| pad.read(0)).collect::<Vec<u8>>();
+        assert_eq!(bits, vec![1, 0, 0, 1, 0, 0, 0, 0]);
+        assert_eq!(pad.read(0), 1);
+    }
+
+    #[test]
+    fn is_strobe_returns_a() {
+        let pad = Pad::default();
+        pad.set_button(Button::A, true);
+        pad.write(1, 0);
+        assert_eq!(pad.read(0), 1);
+        assert_eq!(pad.read(0), 1);
+    }
+
+    #[test]
+    fn is_turbo_synced_to_frame() {
+        let pad = Pad::default();
+        pad.set_turbo(Button::B, true);
+        pad.set_turbo_period(Button::B, 6);
+        let pressed = (0..12)
+            .map(|frame| pad.buttons(frame) & 0x02 != 0)
+            .collect::<Vec<bool>>();
+        assert_eq!(&pressed[0..6], &[true, true, true, false, false, false]);
+        assert_eq!(&pressed[0..6], &pressed[6..12]);
+    }
+}
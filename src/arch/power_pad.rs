@@ -0,0 +1,140 @@
+use std::cell::Cell;
+
+/// マットの面
+/// B面はA面を左右反転したもので四隅が無い
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum Side {
+    #[default]
+    A,
+    B,
+}
+
+/// マットの格子
+pub const COLUMNS: usize = 4;
+pub const ROWS: usize = 3;
+
+/// 読み出し順のボタン番号(A面)
+const BIT3_ORDER: [usize; 8] = [2, 1, 5, 9, 6, 10, 11, 7];
+const BIT4_ORDER: [usize; 4] = [4, 3, 12, 8];
+
+/// パワーパッド / ファミリートレーナー
+/// $4017 Read
+/// - 4 ボタン 4, 3, 12, 8
+/// - 3 ボタン 2, 1, 5, 9, 6, 10, 11, 7
+#[derive(Debug, Default)]
+pub struct PowerPad {
+    pub(crate) side: Cell<Side>,
+    /// bit(n - 1): ボタンn
+    buttons: Cell<u16>,
+    shift3: Cell<u8>,
+    shift4: Cell<u8>,
+    strobe: Cell<bool>,
+}
+
+impl PowerPad {
+    /// 格子上の位置(左上から0-11)に対応するボタン番号
+    /// B面の四隅はNone
+    pub fn button(&self, position: usize) -> Option<usize> {
+        let (row, column) = (position / COLUMNS, position % COLUMNS);
+        match self.side.get() {
+            Side::A => Some(position + 1),
+            Side::B if (row == 0 || row == ROWS - 1) && (column == 0 || column == COLUMNS - 1) => {
+                None
+            }
+            Side::B => Some(row * COLUMNS + (COLUMNS - 1 - column) + 1),
+        }
+    }
+
+    pub fn set_position(&self, position: usize, pressed: bool) {
+        if let Some(button) = self.button(position) {
+            let bit = 1u16 << (button - 1);
+            let buttons = self.buttons.get();
+            self.buttons.set(if pressed {
+                buttons | bit
+            } else {
+                buttons & !bit
+            });
+        }
+    }
+
+    pub fn is_pressed(&self, button: usize) -> bool {
+        self.buttons.get() & (1u16 << (button - 1)) != 0
+    }
+
+    pub fn release_all(&self) {
+        self.buttons.set(0x0000);
+    }
+
+    fn latch(&self) {
+        let serial = |order: &[usize]| {
+            order
+                .iter()
+                .enumerate()
+                .filter(|(_, button)| self.is_pressed(**button))
+                .fold(0u8, |acc, (idx, _)| acc | 1 << idx)
+        };
+        self.shift3.set(serial(&BIT3_ORDER));
+        // 5bit目以降は1
+        self.shift4.set(serial(&BIT4_ORDER) | 0xF0);
+    }
+
+    pub(crate) fn write(&self, value: u8) {
+        let strobe = value & 0x01 != 0;
+        self.strobe.set(strobe);
+        if strobe {
+            self.latch();
+        }
+    }
+
+    pub(crate) fn read(&self) -> u8 {
+        if self.strobe.get() {
+            self.latch();
+        }
+        let (shift3, shift4) = (self.shift3.get(), self.shift4.get());
+        self.shift3.set((shift3 >> 1) | 0x80);
+        self.shift4.set((shift4 >> 1) | 0x80);
+        (shift4 & 0x01) << 4 | (shift3 & 0x01) << 3
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arch::power_pad::{PowerPad, Side};
+
+    #[test]
+    fn is_two_serial_streams() {
+        let power_pad = PowerPad::default();
+        // ボタン2, 8
+        power_pad.set_position(1, true);
+        power_pad.set_position(7, true);
+        power_pad.write(1);
+        power_pad.write(0);
+        let reads = (0..9).map(|_| power_pad.read()).collect::<Vec<u8>>();
+        let bit3 = reads.iter().map(|r| r >> 3 & 0x01).collect::<Vec<u8>>();
+        let bit4 = reads.iter().map(|r| r >> 4 & 0x01).collect::<Vec<u8>>();
+        assert_eq!(bit3, vec![1, 0, 0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(bit4, vec![0, 0, 0, 1, 1, 1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn is_side_b_mirrored() {
+        let power_pad = PowerPad::default();
+        assert_eq!(power_pad.button(0), Some(1));
+        assert_eq!(power_pad.button(11), Some(12));
+
+        power_pad.side.set(Side::B);
+        assert_eq!(power_pad.button(0), None);
+        assert_eq!(power_pad.button(3), None);
+        assert_eq!(power_pad.button(8), None);
+        assert_eq!(power_pad.button(11), None);
+        assert_eq!(power_pad.button(1), Some(3));
+        assert_eq!(power_pad.button(4), Some(8));
+        assert_eq!(power_pad.button(6), Some(6));
+
+        power_pad.set_position(1, true);
+        power_pad.set_position(0, true);
+        assert!(power_pad.is_pressed(3));
+        assert!(!power_pad.is_pressed(1));
+        assert!(!power_pad.is_pressed(2));
+    }
+}
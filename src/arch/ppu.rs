@@ -3,8 +3,10 @@ use sdl2::pixels::PixelFormatEnum;
 use sdl2::rect::Rect;
 use sdl2::render::{Canvas, Texture};
 use sdl2::video::Window;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
 
+use crate::arch::mapper::Cartridge;
 use crate::arch::memory::{PPUMemory, PPURegister};
 use crate::arch::RcRefCell;
 use crate::{
@@ -21,44 +23,69 @@ pub(crate) enum DisplayID {
     DISPLAY4,
 }
 
+/// 描画結果
+/// 入力機器(光線銃)からも参照する
+pub(crate) struct Frame {
+    /// RGB24 256x240
+    pub(crate) buffer: RefCell<Vec<u8>>,
+    /// 描画中のスキャンライン
+    pub(crate) line: Cell<u32>,
+    /// 電源投入からのフレーム数
+    pub(crate) count: Cell<u32>,
+}
+
+impl Default for Frame {
+    fn default() -> Self {
+        Self {
+            buffer: RefCell::new(vec![0x00; DISPLAY_SIZE]),
+            line: Cell::new(0),
+            count: Cell::new(0),
+        }
+    }
+}
+
+impl Frame {
+    /// 画素のRGB
+    pub(crate) fn pixel(&self, x: usize, y: usize) -> [u8; 3] {
+        let offset = (y * DISPLAY_WIDTH + x) * 3;
+        let buffer = self.buffer.borrow();
+        [buffer[offset], buffer[offset + 1], buffer[offset + 2]]
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Mirroring {
     Horizontal,
     Vertial,
+    /// 1画面 $2000
+    OneScreenLower,
+    /// 1画面 $2400
+    OneScreenUpper,
+    /// $2000/$2400/$2800/$2C00ごとのページ
+    Quadrant([usize; 4]),
 }
 
 pub(crate) struct PPU {
-    /// CHR
-    pub(crate) pattern0: Pattern,
     pub(crate) state: RefCell<PPUState>,
     pub(crate) display0: RefCell<Texture>,
     pub(crate) display1: RefCell<Texture>,
-    pub(crate) mirroring: Mirroring,
+    pub(crate) frame: Rc<Frame>,
+    /// CHR, ミラーリング
+    pub(crate) cartridge: Cartridge,
     pub(crate) ioc: RcRefCell<PPURegister>,
     pub(crate) canvas: RcRefCell<Canvas<Window>>,
+    /// フレーム中にパターンが書き換えられた
+    pub(crate) pattern_changed: Cell<bool>,
 }
 
 impl PPU {
     pub fn new(
-        chr: Vec<u8>,
+        cartridge: Cartridge,
         ioc: RcRefCell<PPURegister>,
         canvas: RcRefCell<Canvas<Window>>,
-        mirroring: Mirroring,
+        frame: Rc<Frame>,
     ) -> PPU {
-        fn parse_sprite(buffer: &mut [[u8; SPRITE]; PATTERN_LENGTH], chr: Vec<u8>) {
-            // 16bit -> (8bit, 8bit) -> sprite
-            for (sprite_idx, sprite) in chr.chunks(16).enumerate() {
-                let (pixel0, pixel1) = sprite.split_at(8);
-                for (idx, (pix0, pix1)) in pixel0.iter().zip(pixel1).enumerate() {
-                    // 上位ビットから値を算出
-                    for x in (0..8).rev() {
-                        let b = ((pix0 & 2u8.pow(x)) >> x) + 2 * ((pix1 & 2u8.pow(x)) >> x);
-                        buffer[sprite_idx][8 * idx + (7 - x) as usize] = b;
-                    }
-                }
-            }
-        }
         let state = PPUState::default();
-        let buffer = &mut [[0u8; SPRITE]; PATTERN_LENGTH];
 
         let texture_creator = canvas.borrow().texture_creator();
         let mut texture0 = texture_creator
@@ -77,35 +104,50 @@ impl PPU {
             )
             .unwrap();
 
-        parse_sprite(buffer, chr);
         PPU {
-            pattern0: *buffer,
             state: RefCell::new(state),
             display0: RefCell::new(texture0),
             display1: RefCell::new(texture1),
-            mirroring,
+            frame,
+            cartridge,
             /// I/O CPU Register
             ioc,
             canvas,
+            pattern_changed: Cell::new(false),
         }
     }
 
     pub fn run(&self, cycle: u32) {
         let state = &self.state;
         let line = state.borrow().line;
+        let from = state.borrow().cycle;
         state.borrow_mut().cycle += cycle;
+        self.fetch_hblank(line, from, state.borrow().cycle);
 
         // 341クロックで1line描写
         if state.borrow().cycle >= 341 {
             state.borrow_mut().line += 1;
+            // 描画有効時のみA12が変化する
+            if self.ioc.borrow().ppumask.get() & 0x18 != 0 {
+                self.cartridge.scanline(line);
+            }
             match line {
                 0...239 if line % 8 == 0 => self.sprite_generate(),
                 // 描画
                 240 => self.flush_sprite(),
-                241 => self.ioc.borrow().set_vblank(),
+                241 => {
+                    // CHR-RAMの書き込み, CHRバンクの切り替え
+                    let switched = self.cartridge.chr().is_some_and(|chr| chr.take_switched());
+                    if self.ioc.borrow().ppudata.pattern_dirty.replace(false) || switched {
+                        self.pattern_changed.set(true);
+                    }
+                    self.ioc.borrow().set_vblank();
+                    self.frame.count.set(self.frame.count.get().wrapping_add(1));
+                }
                 262 => state.borrow_mut().line = 0,
                 _ => (),
             };
+            self.frame.line.set(state.borrow().line);
 
             state.borrow_mut().cycle -= 341;
         }
@@ -215,48 +257,108 @@ impl PPU {
         ]
     }
 
+    /// パターンテーブルから1タイル取得
+    /// バンク切り替えに追従するため都度カートリッジから読む
+    pub(crate) fn pattern(&self, tile: usize) -> [u8; SPRITE] {
+        let vram = &self.ioc.borrow().ppudata;
+        let mut buffer = [0u8; SPRITE];
+        // 16bit -> (8bit, 8bit) -> sprite
+        for idx in 0..8 {
+            let pix0 = vram.read(tile * 16 + idx);
+            let pix1 = vram.read(tile * 16 + idx + 8);
+            // 上位ビットから値を算出
+            for x in (0..8).rev() {
+                let b = ((pix0 & 2u8.pow(x)) >> x) + 2 * ((pix1 & 2u8.pow(x)) >> x);
+                buffer[8 * idx + (7 - x) as usize] = b;
+            }
+        }
+        buffer
+    }
+
+    /// 描画時のBGタイル取得
+    /// $2000 bit4でパターンテーブルを選択し, 取得アドレスをカートリッジに通知する
+    fn fetch_pattern(&self, tile: usize) -> [u8; SPRITE] {
+        let tile = if self.ioc.borrow().ppuctrl.get() & 0x10 != 0 {
+            tile | 0x100
+        } else {
+            tile
+        };
+        let pattern = self.pattern(tile);
+        // 各行の上位プレーンが最後の取得
+        for idx in 0..8 {
+            self.cartridge.ppu_fetch(tile * 16 + idx + 8);
+        }
+        pattern
+    }
+
+    /// 水平ブランクのパターン取得をカートリッジに通知する
+    /// スプライトは257ドットから, 次のラインのBGは321ドットから取得
+    /// 8x16スプライトは未使用スロットのタイル$FFで$1000側になる
+    fn fetch_hblank(&self, line: u32, from: u32, to: u32) {
+        if self.ioc.borrow().ppumask.get() & 0x18 == 0 || (line >= 240 && line != 261) {
+            return;
+        }
+        let ctrl = self.ioc.borrow().ppuctrl.get();
+        let sprite = if ctrl & 0x28 != 0 { 0x1FF0 } else { 0x0FF0 };
+        let bg = if ctrl & 0x10 != 0 { 0x1000 } else { 0x0000 };
+        if from < 260 && to >= 260 {
+            self.cartridge.ppu_fetch(sprite);
+        }
+        if from < 324 && to >= 324 {
+            self.cartridge.ppu_fetch(bg);
+        }
+    }
+
+    /// 前回の呼び出し以降にパターンが書き換えられたか
+    /// 書き込みごとではなくフレーム単位で通知する
+    pub fn take_pattern_changed(&self) -> bool {
+        self.pattern_changed.replace(false)
+    }
+
     pub fn sprite_flush(&self) -> Pattern {
-        self.pattern0
+        let mut buffer = [[0u8; SPRITE]; PATTERN_LENGTH];
+        for (tile, sprite) in buffer.iter_mut().enumerate() {
+            *sprite = self.pattern(tile);
+        }
+        buffer
     }
 
     pub(crate) fn sprite_generate(&self) {
         let vram = &self.ioc.borrow().ppudata;
 
         let line = self.state.borrow().line as usize / 8usize;
-        self.display0
-            .borrow_mut()
-            .with_lock(None, |buffer: &mut [u8], pitch: usize| {
-                for idx in 0..DISPLAY_SPRITE_WIDTH {
-                    let sprite_idx = line * DISPLAY_SPRITE_WIDTH + idx;
-                    let color = self.get_attribute(
-                        line,
-                        sprite_idx % DISPLAY_SPRITE_WIDTH,
-                        DisplayID::DISPLAY1,
-                    );
-                    for (pixel_idx, pixel) in self.pattern0[vram.read(sprite_idx + 0x2400) as usize]
-                        .iter()
-                        .enumerate()
-                    {
-                        let sprite_x_idx = sprite_idx % DISPLAY_SPRITE_WIDTH;
-                        let sprite_y_idx = line;
-                        let pixel_x_idx = pixel_idx % SPRITE_SIDE;
-                        let pixel_y_idx = pixel_idx / SPRITE_SIDE;
+        let buffer = &mut self.frame.buffer.borrow_mut();
+        for idx in 0..DISPLAY_SPRITE_WIDTH {
+            let sprite_idx = line * DISPLAY_SPRITE_WIDTH + idx;
+            // ネームテーブル -> 属性 -> パターンの順に取得
+            let addr = sprite_idx + 0x2400;
+            self.cartridge.ppu_fetch(addr);
+            let tile = vram.read(addr) as usize;
+            let color =
+                self.get_attribute(line, sprite_idx % DISPLAY_SPRITE_WIDTH, DisplayID::DISPLAY1);
+            for (pixel_idx, pixel) in self.fetch_pattern(tile).iter().enumerate() {
+                let sprite_x_idx = sprite_idx % DISPLAY_SPRITE_WIDTH;
+                let sprite_y_idx = line;
+                let pixel_x_idx = pixel_idx % SPRITE_SIDE;
+                let pixel_y_idx = pixel_idx / SPRITE_SIDE;
 
-                        let offset = sprite_x_idx * SPRITE_SIDE * 3
-                            + sprite_y_idx * 3 * DISPLAY_WIDTH * 8
-                            + pixel_x_idx * 3
-                            + pixel_y_idx * 3 * SPRITE_SIDE * DISPLAY_SPRITE_WIDTH;
+                let offset = sprite_x_idx * SPRITE_SIDE * 3
+                    + sprite_y_idx * 3 * DISPLAY_WIDTH * 8
+                    + pixel_x_idx * 3
+                    + pixel_y_idx * 3 * SPRITE_SIDE * DISPLAY_SPRITE_WIDTH;
 
-                        buffer[offset] = color[*pixel as usize][0];
-                        buffer[offset + 1] = color[*pixel as usize][1];
-                        buffer[offset + 2] = color[*pixel as usize][2];
-                    }
-                }
-            })
-            .unwrap();
+                buffer[offset] = color[*pixel as usize][0];
+                buffer[offset + 1] = color[*pixel as usize][1];
+                buffer[offset + 2] = color[*pixel as usize][2];
+            }
+        }
     }
 
     fn flush_sprite(&self) {
+        self.display0
+            .borrow_mut()
+            .update(None, &self.frame.buffer.borrow(), DISPLAY_WIDTH * 3)
+            .unwrap();
         self.canvas
             .borrow_mut()
             .copy(
@@ -273,7 +375,7 @@ impl PPU {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) struct PPUState {
     cycle: u32,
     line: u32,
@@ -0,0 +1,163 @@
+use std::cell::{Cell, RefCell};
+
+use crate::arch::apu::CPU_CLOCK;
+use crate::wav::Wave;
+
+/// 録音時のサンプリングレート
+const RECORD_RATE: u32 = 44_100;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TapeState {
+    Stop,
+    Play,
+    Record,
+}
+
+/// データレコーダ
+/// $4016 Write bit2: 出力
+/// $4016 Read bit1: 入力
+pub struct DataRecorder {
+    state: Cell<TapeState>,
+    tape: RefCell<Wave>,
+    /// 再生/録音位置
+    position: Cell<usize>,
+    /// 1サンプル分のクロック
+    cycle: Cell<u32>,
+    output: Cell<bool>,
+}
+
+impl Default for DataRecorder {
+    fn default() -> Self {
+        Self {
+            state: Cell::new(TapeState::Stop),
+            tape: RefCell::new(Wave {
+                rate: RECORD_RATE,
+                samples: Vec::new(),
+            }),
+            position: Cell::new(0),
+            cycle: Cell::new(0),
+            output: Cell::new(false),
+        }
+    }
+}
+
+impl DataRecorder {
+    pub fn state(&self) -> TapeState {
+        self.state.get()
+    }
+
+    pub fn play(&self, tape: Wave) {
+        self.tape.replace(tape);
+        self.position.set(0);
+        self.cycle.set(0);
+        self.state.set(TapeState::Play);
+    }
+
+    pub fn record(&self) {
+        self.tape.replace(Wave {
+            rate: RECORD_RATE,
+            samples: Vec::new(),
+        });
+        self.position.set(0);
+        self.cycle.set(0);
+        self.state.set(TapeState::Record);
+    }
+
+    /// 録音していた場合はその内容を返す
+    pub fn stop(&self) -> Option<Wave> {
+        let state = self.state.replace(TapeState::Stop);
+        if state == TapeState::Record {
+            Some(self.tape.replace(Wave {
+                rate: RECORD_RATE,
+                samples: Vec::new(),
+            }))
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn write(&self, value: u8) {
+        self.output.set(value & 0x04 != 0);
+    }
+
+    pub(crate) fn read(&self) -> u8 {
+        if self.state.get() != TapeState::Play {
+            return 0x00;
+        }
+        let tape = self.tape.borrow();
+        match tape.samples.get(self.position.get()) {
+            Some(sample) if *sample >= 0x80 => 0x02,
+            _ => 0x00,
+        }
+    }
+
+    pub(crate) fn clock(&self, cycle: u32) {
+        if self.state.get() == TapeState::Stop {
+            return;
+        }
+        let rate = self.tape.borrow().rate.max(1);
+        let mut total = self.cycle.get() + cycle * rate;
+        while total >= CPU_CLOCK {
+            total -= CPU_CLOCK;
+            match self.state.get() {
+                TapeState::Play => {
+                    self.position.set(self.position.get() + 1);
+                    if self.position.get() >= self.tape.borrow().samples.len() {
+                        self.state.set(TapeState::Stop);
+                    }
+                }
+                TapeState::Record => {
+                    let sample = if self.output.get() { 0xC0 } else { 0x40 };
+                    self.tape.borrow_mut().samples.push(sample);
+                }
+                TapeState::Stop => (),
+            }
+        }
+        self.cycle.set(total);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arch::apu::CPU_CLOCK;
+    use crate::arch::recorder::{DataRecorder, TapeState, RECORD_RATE};
+    use crate::wav::Wave;
+
+    /// 1サンプル分より少し長いクロック
+    const SAMPLE_CYCLE: u32 = CPU_CLOCK / RECORD_RATE + 1;
+
+    #[test]
+    fn is_record_output_bit() {
+        let recorder = DataRecorder::default();
+        recorder.record();
+        for value in [0x04, 0x00, 0x04].iter() {
+            recorder.write(*value);
+            recorder.clock(SAMPLE_CYCLE);
+        }
+        let tape = recorder.stop().unwrap();
+        assert_eq!(tape.rate, RECORD_RATE);
+        assert_eq!(tape.samples, vec![0xC0, 0x40, 0xC0]);
+        assert_eq!(recorder.state(), TapeState::Stop);
+        assert!(recorder.stop().is_none());
+    }
+
+    #[test]
+    fn is_play_input_bit() {
+        let recorder = DataRecorder::default();
+        recorder.play(Wave {
+            rate: CPU_CLOCK,
+            samples: vec![0x40, 0xC0, 0x7F, 0x80],
+        });
+        let bits = (0..4)
+            .map(|_| {
+                let bit = recorder.read();
+                recorder.clock(1);
+                bit
+            })
+            .collect::<Vec<u8>>();
+        assert_eq!(bits, vec![0x00, 0x02, 0x00, 0x02]);
+        // 最後まで再生したら停止
+        assert_eq!(recorder.state(), TapeState::Stop);
+        assert_eq!(recorder.read(), 0x00);
+    }
+}
@@ -100,3 +100,28 @@ impl Default for State {
         }
     }
 }
+
+impl State {
+    /// スタック退避用
+    /// bit5は常に1, bit4はBフラグ
+    pub(crate) fn to_u8(self) -> u8 {
+        (self.n as u8) << 7
+            | (self.v as u8) << 6
+            | 0x20
+            | (self.b as u8) << 4
+            | (self.i as u8) << 2
+            | (self.z as u8) << 1
+            | self.c as u8
+    }
+
+    pub(crate) fn from_u8(value: u8) -> State {
+        State {
+            n: value & 0x80 != 0,
+            v: value & 0x40 != 0,
+            b: value & 0x10 != 0,
+            i: value & 0x04 != 0,
+            z: value & 0x02 != 0,
+            c: value & 0x01 != 0,
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+
+/// メモリ上のステートセーブ
+/// ファイルには書き出さない
+#[derive(Default)]
+pub struct Snapshot(Vec<Box<dyn Any>>);
+
+/// 各部品は保存, 復元とも同じ順序で自身のCell, RefCellを渡す
+pub(crate) enum StateIo<'a> {
+    Save(&'a mut Vec<Box<dyn Any>>),
+    Load(std::slice::Iter<'a, Box<dyn Any>>),
+}
+
+impl<'a> StateIo<'a> {
+    pub(crate) fn save(snapshot: &'a mut Snapshot) -> StateIo<'a> {
+        StateIo::Save(&mut snapshot.0)
+    }
+
+    pub(crate) fn load(snapshot: &'a Snapshot) -> StateIo<'a> {
+        StateIo::Load(snapshot.0.iter())
+    }
+
+    /// 型が合わなければ復元しない
+    fn next<T: 'static>(&mut self) -> Option<&'a T> {
+        match self {
+            StateIo::Save(_) => None,
+            StateIo::Load(values) => values.next().and_then(|value| value.downcast_ref::<T>()),
+        }
+    }
+
+    pub(crate) fn cell<T: Copy + 'static>(&mut self, cell: &Cell<T>) {
+        if let StateIo::Save(values) = self {
+            values.push(Box::new(cell.get()));
+        } else if let Some(value) = self.next::<T>() {
+            cell.set(*value);
+        }
+    }
+
+    pub(crate) fn cells<T: Copy + 'static>(&mut self, cells: &[Cell<T>]) {
+        cells.iter().for_each(|cell| self.cell(cell));
+    }
+
+    pub(crate) fn ref_cell<T: Clone + 'static>(&mut self, cell: &RefCell<T>) {
+        if let StateIo::Save(values) = self {
+            values.push(Box::new(cell.borrow().clone()));
+        } else if let Some(value) = self.next::<T>() {
+            *cell.borrow_mut() = value.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+
+    use crate::arch::snapshot::{Snapshot, StateIo};
+
+    #[test]
+    fn is_restore_in_order() {
+        let bank = Cell::new(0x03u8);
+        let banks = [Cell::new(1u16), Cell::new(2u16)];
+        let ram = RefCell::new(vec![0xEA; 4]);
+        let visit = |io: &mut StateIo| {
+            io.cell(&bank);
+            io.cells(&banks);
+            io.ref_cell(&ram);
+        };
+        let mut snapshot = Snapshot::default();
+        visit(&mut StateIo::save(&mut snapshot));
+
+        bank.set(0x00);
+        banks[1].set(0x00);
+        ram.borrow_mut()[0] = 0x00;
+        visit(&mut StateIo::load(&snapshot));
+        assert_eq!(bank.get(), 0x03);
+        assert_eq!(banks[1].get(), 2);
+        assert_eq!(ram.borrow()[0], 0xEA);
+    }
+}
@@ -0,0 +1,144 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+use crate::arch::ppu::Frame;
+use crate::{DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+/// 受光素子の残光
+/// 描画からこのライン数の間だけ光を検出する
+const LIGHT_PERSISTENCE: usize = 26;
+/// 照準周辺の判定範囲
+const LIGHT_RADIUS: usize = 2;
+/// 明るさのしきい値
+const LIGHT_THRESHOLD: usize = 0xC0;
+
+/// 光線銃
+/// $4017 Read
+/// - 4 trigger (1: pulled)
+/// - 3 light sense (0: detected)
+pub struct Zapper {
+    trigger: Cell<bool>,
+    /// 画面上の照準 (x, y)
+    cursor: Cell<Option<(usize, usize)>>,
+    frame: Rc<Frame>,
+}
+
+impl Zapper {
+    pub(crate) fn new(frame: Rc<Frame>) -> Zapper {
+        Zapper {
+            trigger: Cell::new(false),
+            cursor: Cell::new(None),
+            frame,
+        }
+    }
+
+    pub fn set_trigger(&self, pulled: bool) {
+        self.trigger.set(pulled);
+    }
+
+    /// 画面外はNone
+    pub fn set_cursor(&self, cursor: Option<(usize, usize)>) {
+        let cursor = cursor.filter(|(x, y)| *x < DISPLAY_WIDTH && *y < DISPLAY_HEIGHT);
+        self.cursor.set(cursor);
+    }
+
+    fn light(&self) -> bool {
+        let (x, y) = match self.cursor.get() {
+            Some(cursor) => cursor,
+            None => return false,
+        };
+
+        // 照準のラインが描画された直後のみ
+        let line = self.frame.line.get() as usize;
+        if line < y || line >= y + LIGHT_PERSISTENCE {
+            return false;
+        }
+
+        let xs = x.saturating_sub(LIGHT_RADIUS)..(x + LIGHT_RADIUS + 1).min(DISPLAY_WIDTH);
+        let ys = y.saturating_sub(LIGHT_RADIUS)..(y + LIGHT_RADIUS + 1).min(DISPLAY_HEIGHT);
+        let (sum, count) = ys
+            .flat_map(|y| xs.clone().map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let [r, g, b] = self.frame.pixel(x, y);
+                (usize::from(r) + usize::from(g) + usize::from(b)) / 3
+            })
+            .fold((0, 0), |(sum, count), v| (sum + v, count + 1));
+        sum >= LIGHT_THRESHOLD * count
+    }
+
+    pub(crate) fn read(&self) -> u8 {
+        let trigger = if self.trigger.get() { 0x10 } else { 0x00 };
+        let light = if self.light() { 0x00 } else { 0x08 };
+        trigger | light
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use crate::arch::ppu::Frame;
+    use crate::arch::zapper::Zapper;
+    use crate::DISPLAY_WIDTH;
+
+    /// (x, y)を中心とする5x5を塗る
+    fn zapper(x: usize, y: usize, value: u8) -> Zapper {
+        let frame = Rc::new(Frame::default());
+        {
+            let mut buffer = frame.buffer.borrow_mut();
+            for y in y - 2..=y + 2 {
+                for x in x - 2..=x + 2 {
+                    let offset = (y * DISPLAY_WIDTH + x) * 3;
+                    buffer[offset..offset + 3].copy_from_slice(&[value; 3]);
+                }
+            }
+        }
+        Zapper::new(frame)
+    }
+
+    #[test]
+    fn is_light_threshold() {
+        let zapper = zapper(100, 50, 0xC0);
+        zapper.set_cursor(Some((100, 50)));
+        zapper.frame.line.set(50);
+        assert_eq!(zapper.read() & 0x08, 0x00);
+
+        // 平均が0xC0を下回る
+        let offset = (50 * DISPLAY_WIDTH + 100) * 3;
+        zapper.frame.buffer.borrow_mut()[offset..offset + 3].copy_from_slice(&[0xBF; 3]);
+        assert_eq!(zapper.read() & 0x08, 0x08);
+    }
+
+    #[test]
+    fn is_light_window() {
+        let zapper = zapper(100, 50, 0xFF);
+        zapper.set_cursor(Some((100, 50)));
+        let light = |line: u32| {
+            zapper.frame.line.set(line);
+            zapper.read() & 0x08 == 0x00
+        };
+        assert!(!light(49));
+        assert!(light(50));
+        assert!(light(75));
+        assert!(!light(76));
+    }
+
+    #[test]
+    fn is_no_cursor() {
+        let zapper = zapper(100, 50, 0xFF);
+        zapper.frame.line.set(50);
+        assert_eq!(zapper.read(), 0x08);
+        // 画面外
+        zapper.set_cursor(Some((DISPLAY_WIDTH, 50)));
+        assert_eq!(zapper.read(), 0x08);
+    }
+
+    #[test]
+    fn is_trigger() {
+        let zapper = zapper(100, 50, 0x00);
+        zapper.set_trigger(true);
+        assert_eq!(zapper.read(), 0x18);
+        zapper.set_trigger(false);
+        assert_eq!(zapper.read(), 0x08);
+    }
+}
@@ -8,6 +8,7 @@ extern crate sdl2;
 pub mod arch;
 pub mod parser;
 pub mod ui;
+pub mod wav;
 
 const SPRITE_SIDE: usize = 8;
 const SPRITE: usize = SPRITE_SIDE * SPRITE_SIDE;
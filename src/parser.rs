@@ -2,36 +2,115 @@ use std::fs;
 use std::io::prelude::Read;
 use std::io::BufReader;
 
-pub fn parser(path: &str) -> Result<(Vec<u8>, Vec<u8>, u8), String> {
+use crate::arch::ppu::Mirroring;
+
+/// iNESヘッダ + PRG/CHR
+pub struct INes {
+    /// 16Byteヘッダ
+    pub header: [u8; 16],
+    /// flag6 bit2, $7000-$71FFに配置する512Byte
+    pub trainer: Vec<u8>,
+    pub prg: Vec<u8>,
+    pub chr: Vec<u8>,
+}
+
+impl INes {
+    pub fn flag6(&self) -> u8 {
+        self.header[6]
+    }
+
+    pub fn flag7(&self) -> u8 {
+        self.header[7]
+    }
+
+    /// flag6上位4bit + flag7上位4bit
+    pub fn mapper(&self) -> u16 {
+        u16::from(self.flag6() >> 4) | u16::from(self.flag7() & 0xF0)
+    }
+
+    /// flag7 bit2-3 == 0b10
+    pub fn is_nes2(&self) -> bool {
+        self.flag7() & 0x0C == 0x08
+    }
+
+    /// NES 2.0: byte8上位4bit
+    pub fn submapper(&self) -> u8 {
+        if self.is_nes2() {
+            self.header[8] >> 4
+        } else {
+            0
+        }
+    }
+
+    /// PRG-RAMのサイズ
+    /// NES 2.0: byte10 (64 << n), iNES: byte8 (8KB単位, 0は8KB)
+    pub fn prg_ram_size(&self) -> usize {
+        if self.is_nes2() {
+            let size = |shift: u8| if shift == 0 { 0 } else { 64usize << shift };
+            size(self.header[10] & 0x0F) + size(self.header[10] >> 4)
+        } else {
+            0x2000 * (self.header[8] as usize).max(1)
+        }
+    }
+
+    /// flag6 bit1
+    pub fn has_battery(&self) -> bool {
+        self.flag6() & 0x02 != 0
+    }
+
+    /// PRG + CHRのCRC-32 (カートリッジDBとの照合用)
+    pub fn crc32(&self) -> u32 {
+        !self
+            .prg
+            .iter()
+            .chain(self.chr.iter())
+            .fold(!0u32, |crc, b| {
+                (0..8).fold(crc ^ u32::from(*b), |crc, _| {
+                    (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg())
+                })
+            })
+    }
+
+    /// flag6 bit0
+    pub fn mirroring(&self) -> Mirroring {
+        if self.flag6() & 0x01 != 0 {
+            Mirroring::Vertial
+        } else {
+            Mirroring::Horizontal
+        }
+    }
+}
+
+pub fn parser(path: &str) -> Result<INes, String> {
     let f = fs::File::open(path).map_err(|v| format!("{}", v))?;
     let mut reader = BufReader::new(f).bytes();
 
+    let mut header = [0u8; 16];
+    for (idx, b) in reader.by_ref().take(16).enumerate() {
+        header[idx] = b.map_err(|err| err.to_string())?;
+    }
+
     // header verify
     static VERIFY: &'static [u8; 4] = &[0x4E, 0x45, 0x53, 0x1A];
-    for (b, v) in reader.by_ref().take(4).zip(VERIFY) {
-        if let Ok(ref b) = b {
-            if b != v {
-                return Err("err".to_string());
-            }
-        }
+    if &header[0..4] != VERIFY {
+        return Err("err".to_string());
     }
 
+    let trainer_size = if header[6] & 0x04 != 0 { 0x200 } else { 0 };
+    let trainer = reader
+        .by_ref()
+        .take(trainer_size)
+        .collect::<Result<Vec<u8>, _>>()
+        .map_err(|err| err.to_string())?;
+
     // prg,chr pages
-    static HEADER_END: usize = 0x0009;
     static PRG_SIZE: usize = 0x4000;
     static CHR_SIZE: usize = 0x2000;
-    let prg_pages = (reader.next().ok_or("parse")?)
-        .map_err(|err| err.to_string())
-        .map(|b| PRG_SIZE * b as usize)?;
-    let chr_pages = (reader.next().ok_or("parse")?)
-        .map_err(|err| err.to_string())
-        .map(|b| CHR_SIZE * b as usize)?;
-
-    let flag6 = (reader.next().ok_or("parse")?).map_err(|err| err.to_string())?;
+    let prg_pages = PRG_SIZE * header[4] as usize;
+    let chr_pages = CHR_SIZE * header[5] as usize;
 
     let prg = reader
         .by_ref()
-        .skip(HEADER_END)
         .take(prg_pages)
         .collect::<Result<Vec<u8>, _>>()
         .map_err(|err| err.to_string())?;
@@ -41,5 +120,18 @@ pub fn parser(path: &str) -> Result<(Vec<u8>, Vec<u8>, u8), String> {
         .collect::<Result<Vec<u8>, _>>()
         .map_err(|err| err.to_string())?;
 
-    Ok((prg, chr, flag6))
+    // マッパーは空, 途中で切れたPRGを扱えない
+    if prg.is_empty() || prg.len() < prg_pages {
+        return Err(format!("prg: {} bytes, expected {}", prg.len(), prg_pages));
+    }
+    if chr.len() < chr_pages {
+        return Err(format!("chr: {} bytes, expected {}", chr.len(), chr_pages));
+    }
+
+    Ok(INes {
+        header,
+        trainer,
+        prg,
+        chr,
+    })
 }
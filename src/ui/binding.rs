@@ -0,0 +1,499 @@
+use sdl2::keyboard::{Keycode, Mod};
+use std::collections::HashMap;
+use std::fs;
+
+use crate::arch::pad::Button;
+use crate::arch::power_pad::{COLUMNS, ROWS};
+
+pub const BINDINGS_PATH: &str = "./bindings.cfg";
+
+/// エミュレータ側の操作
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Hotkey {
+    Quit,
+    Reset,
+    Pause,
+    SaveState,
+    LoadState,
+    FastForward,
+    Screenshot,
+    Rebind,
+    TapePlay,
+    TapeRecord,
+    TapeStop,
+}
+
+impl Hotkey {
+    pub const ALL: [Hotkey; 11] = [
+        Hotkey::Quit,
+        Hotkey::Reset,
+        Hotkey::Pause,
+        Hotkey::SaveState,
+        Hotkey::LoadState,
+        Hotkey::FastForward,
+        Hotkey::Screenshot,
+        Hotkey::Rebind,
+        Hotkey::TapePlay,
+        Hotkey::TapeRecord,
+        Hotkey::TapeStop,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Hotkey::Quit => "quit",
+            Hotkey::Reset => "reset",
+            Hotkey::Pause => "pause",
+            Hotkey::SaveState => "save_state",
+            Hotkey::LoadState => "load_state",
+            Hotkey::FastForward => "fast_forward",
+            Hotkey::Screenshot => "screenshot",
+            Hotkey::Rebind => "rebind",
+            Hotkey::TapePlay => "tape_play",
+            Hotkey::TapeRecord => "tape_record",
+            Hotkey::TapeStop => "tape_stop",
+        }
+    }
+}
+
+fn button_name(button: Button) -> &'static str {
+    match button {
+        Button::A => "a",
+        Button::B => "b",
+        Button::Select => "select",
+        Button::Start => "start",
+        Button::Up => "up",
+        Button::Down => "down",
+        Button::Left => "left",
+        Button::Right => "right",
+    }
+}
+
+/// ファミリーベーシック キーボードの配列
+/// [行][列0の4キー, 列1の4キー]
+const FAMILY_BASIC_KEYS: [[Keycode; 8]; 9] = [
+    // ] [ RETURN F8 STOP ¥ RSHIFT カナ
+    [
+        Keycode::RightBracket,
+        Keycode::LeftBracket,
+        Keycode::Return,
+        Keycode::F8,
+        Keycode::End,
+        Keycode::Backslash,
+        Keycode::RShift,
+        Keycode::LAlt,
+    ],
+    // ; : @ F7 ^ - / _
+    [
+        Keycode::Semicolon,
+        Keycode::Quote,
+        Keycode::Backquote,
+        Keycode::F7,
+        Keycode::Equals,
+        Keycode::Minus,
+        Keycode::Slash,
+        Keycode::RAlt,
+    ],
+    [
+        Keycode::K,
+        Keycode::L,
+        Keycode::O,
+        Keycode::F6,
+        Keycode::Num0,
+        Keycode::P,
+        Keycode::Comma,
+        Keycode::Period,
+    ],
+    [
+        Keycode::J,
+        Keycode::U,
+        Keycode::I,
+        Keycode::F5,
+        Keycode::Num8,
+        Keycode::Num9,
+        Keycode::N,
+        Keycode::M,
+    ],
+    [
+        Keycode::H,
+        Keycode::G,
+        Keycode::Y,
+        Keycode::F4,
+        Keycode::Num6,
+        Keycode::Num7,
+        Keycode::V,
+        Keycode::B,
+    ],
+    [
+        Keycode::D,
+        Keycode::R,
+        Keycode::T,
+        Keycode::F3,
+        Keycode::Num4,
+        Keycode::Num5,
+        Keycode::C,
+        Keycode::F,
+    ],
+    [
+        Keycode::A,
+        Keycode::S,
+        Keycode::W,
+        Keycode::F2,
+        Keycode::Num3,
+        Keycode::E,
+        Keycode::Z,
+        Keycode::X,
+    ],
+    // CTR Q ESC F1 2 1 GRPH LSHIFT
+    [
+        Keycode::LCtrl,
+        Keycode::Q,
+        Keycode::Escape,
+        Keycode::F1,
+        Keycode::Num2,
+        Keycode::Num1,
+        Keycode::RCtrl,
+        Keycode::LShift,
+    ],
+    // ← → ↑ CLR HOME INS DEL SPACE ↓
+    [
+        Keycode::Left,
+        Keycode::Right,
+        Keycode::Up,
+        Keycode::Home,
+        Keycode::Insert,
+        Keycode::Backspace,
+        Keycode::Space,
+        Keycode::Down,
+    ],
+];
+
+/// ホストのキー -> (行, キー番号)
+/// キーボード接続中は割り当てより優先する
+/// GUIキー(Windows/Command)を押している間は割り当て側で受ける
+pub fn family_basic_key(key: Keycode, keymod: Mod) -> Option<(usize, usize)> {
+    if keymod.intersects(Mod::LGUIMOD | Mod::RGUIMOD) {
+        return None;
+    }
+    FAMILY_BASIC_KEYS
+        .iter()
+        .enumerate()
+        .find_map(|(row, keys)| keys.iter().position(|k| *k == key).map(|idx| (row, idx)))
+}
+
+/// キーに割り当てられる操作
+/// Pad(ポート番号, ボタン)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    Pad(usize, Button),
+    /// 連射 (A, Bのみ)
+    Turbo(usize, Button),
+    /// パワーパッドの格子位置 (左上から0-11)
+    PowerPad(usize),
+    Hotkey(Hotkey),
+}
+
+impl Action {
+    /// 再割り当て時の順番
+    pub fn all() -> Vec<Action> {
+        let pads = (0..4).flat_map(|port| Button::ALL.iter().map(move |b| Action::Pad(port, *b)));
+        let turbo = (0..4).flat_map(|port| {
+            [Button::A, Button::B]
+                .iter()
+                .map(move |b| Action::Turbo(port, *b))
+                .collect::<Vec<Action>>()
+        });
+        let power_pad = (0..COLUMNS * ROWS).map(Action::PowerPad);
+        let hotkeys = Hotkey::ALL.iter().map(|h| Action::Hotkey(*h));
+        pads.chain(turbo).chain(power_pad).chain(hotkeys).collect()
+    }
+
+    /// 設定ファイル上の名前
+    /// pad1.a, hotkey.quit
+    pub fn name(self) -> String {
+        match self {
+            Action::Pad(port, button) => format!("pad{}.{}", port + 1, button_name(button)),
+            Action::Turbo(port, button) => {
+                format!("pad{}.turbo_{}", port + 1, button_name(button))
+            }
+            Action::PowerPad(position) => format!("power_pad.{}", position + 1),
+            Action::Hotkey(hotkey) => format!("hotkey.{}", hotkey.name()),
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::all()
+            .into_iter()
+            .find(|action| action.name() == name)
+    }
+}
+
+/// キー割り当て
+/// 1つの操作に複数のキーを割り当て可能
+#[derive(Debug, Clone)]
+pub struct Bindings(HashMap<Keycode, Action>);
+
+impl Default for Bindings {
+    fn default() -> Self {
+        use Button::*;
+        let keys = [
+            (Keycode::X, Action::Pad(0, A)),
+            (Keycode::Z, Action::Pad(0, B)),
+            (Keycode::RShift, Action::Pad(0, Select)),
+            (Keycode::Return, Action::Pad(0, Start)),
+            (Keycode::Up, Action::Pad(0, Up)),
+            (Keycode::Down, Action::Pad(0, Down)),
+            (Keycode::Left, Action::Pad(0, Left)),
+            (Keycode::Right, Action::Pad(0, Right)),
+            (Keycode::D, Action::Turbo(0, A)),
+            (Keycode::A, Action::Turbo(0, B)),
+            (Keycode::K, Action::Pad(1, A)),
+            (Keycode::J, Action::Pad(1, B)),
+            (Keycode::U, Action::Pad(1, Select)),
+            (Keycode::I, Action::Pad(1, Start)),
+            (Keycode::T, Action::Pad(1, Up)),
+            (Keycode::G, Action::Pad(1, Down)),
+            (Keycode::F, Action::Pad(1, Left)),
+            (Keycode::H, Action::Pad(1, Right)),
+            (Keycode::Kp3, Action::Pad(2, A)),
+            (Keycode::Kp1, Action::Pad(2, B)),
+            (Keycode::Kp7, Action::Pad(2, Select)),
+            (Keycode::Kp9, Action::Pad(2, Start)),
+            (Keycode::Kp8, Action::Pad(2, Up)),
+            (Keycode::Kp2, Action::Pad(2, Down)),
+            (Keycode::Kp4, Action::Pad(2, Left)),
+            (Keycode::Kp6, Action::Pad(2, Right)),
+            (Keycode::PageUp, Action::Pad(3, A)),
+            (Keycode::Insert, Action::Pad(3, B)),
+            (Keycode::Num9, Action::Pad(3, Select)),
+            (Keycode::Num0, Action::Pad(3, Start)),
+            (Keycode::Home, Action::Pad(3, Up)),
+            (Keycode::End, Action::Pad(3, Down)),
+            (Keycode::Delete, Action::Pad(3, Left)),
+            (Keycode::PageDown, Action::Pad(3, Right)),
+            (Keycode::Num1, Action::PowerPad(0)),
+            (Keycode::Num2, Action::PowerPad(1)),
+            (Keycode::Num3, Action::PowerPad(2)),
+            (Keycode::Num4, Action::PowerPad(3)),
+            (Keycode::Num5, Action::PowerPad(4)),
+            (Keycode::Num6, Action::PowerPad(5)),
+            (Keycode::Num7, Action::PowerPad(6)),
+            (Keycode::Num8, Action::PowerPad(7)),
+            (Keycode::C, Action::PowerPad(8)),
+            (Keycode::V, Action::PowerPad(9)),
+            (Keycode::B, Action::PowerPad(10)),
+            (Keycode::N, Action::PowerPad(11)),
+            (Keycode::Escape, Action::Hotkey(Hotkey::Quit)),
+            (Keycode::Q, Action::Hotkey(Hotkey::Quit)),
+            (Keycode::R, Action::Hotkey(Hotkey::Reset)),
+            (Keycode::P, Action::Hotkey(Hotkey::Pause)),
+            (Keycode::F5, Action::Hotkey(Hotkey::SaveState)),
+            (Keycode::F7, Action::Hotkey(Hotkey::LoadState)),
+            (Keycode::Tab, Action::Hotkey(Hotkey::FastForward)),
+            (Keycode::F12, Action::Hotkey(Hotkey::Screenshot)),
+            (Keycode::S, Action::Hotkey(Hotkey::Rebind)),
+            (Keycode::F9, Action::Hotkey(Hotkey::TapePlay)),
+            (Keycode::F10, Action::Hotkey(Hotkey::TapeRecord)),
+            (Keycode::F11, Action::Hotkey(Hotkey::TapeStop)),
+        ];
+        Bindings(keys.iter().cloned().collect())
+    }
+}
+
+impl Bindings {
+    pub fn action(&self, key: Keycode) -> Option<Action> {
+        self.0.get(&key).cloned()
+    }
+
+    pub fn keys(&self, action: Action) -> Vec<Keycode> {
+        self.0
+            .iter()
+            .filter(|(_, a)| **a == action)
+            .map(|(k, _)| *k)
+            .collect()
+    }
+
+    /// 既存の割り当てを置き換える
+    pub fn bind(&mut self, action: Action, key: Keycode) {
+        self.0.retain(|_, a| *a != action);
+        self.0.insert(key, action);
+    }
+
+    /// 1行1割り当て
+    /// <Key名> = <操作名>
+    pub fn parse(text: &str) -> Result<Bindings, String> {
+        let mut map = HashMap::new();
+        for (line_no, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut kv = line.splitn(2, '=');
+            let key = kv.next().map(str::trim).unwrap_or("");
+            let action = kv
+                .next()
+                .map(str::trim)
+                .ok_or_else(|| format!("line {}: missing '='", line_no + 1))?;
+            let key = Keycode::from_name(key)
+                .ok_or_else(|| format!("line {}: unknown key {}", line_no + 1, key))?;
+            let action = Action::from_name(action)
+                .ok_or_else(|| format!("line {}: unknown action {}", line_no + 1, action))?;
+            map.insert(key, action);
+        }
+        Ok(Bindings(map))
+    }
+
+    pub fn serialize(&self) -> String {
+        let mut lines = Action::all()
+            .into_iter()
+            .flat_map(|action| {
+                self.keys(action)
+                    .into_iter()
+                    .map(move |key| format!("{} = {}", key.name(), action.name()))
+            })
+            .collect::<Vec<String>>();
+        lines.sort();
+        lines.join("\n") + "\n"
+    }
+
+    pub fn load(path: &str) -> Result<Bindings, String> {
+        let text = fs::read_to_string(path).map_err(|err| err.to_string())?;
+        Bindings::parse(&text)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        fs::write(path, self.serialize()).map_err(|err| err.to_string())
+    }
+}
+
+/// 設定画面での再割り当て
+/// 全操作を順番に1キーずつ受け付ける
+pub(crate) struct Rebinding {
+    actions: Vec<Action>,
+    idx: usize,
+    bindings: Bindings,
+}
+
+pub(crate) enum RebindState {
+    Next(Action),
+    Done(Bindings),
+    Canceled,
+}
+
+impl Rebinding {
+    pub(crate) fn new(bindings: &Bindings) -> Rebinding {
+        Rebinding {
+            actions: Action::all(),
+            idx: 0,
+            bindings: bindings.clone(),
+        }
+    }
+
+    pub(crate) fn current(&self) -> Action {
+        self.actions[self.idx]
+    }
+
+    /// Escapeで中断, Backspaceで現在の割り当てを維持
+    /// このため両キーはここでは操作に割り当てられない (設定ファイルでは可能)
+    pub(crate) fn input(&mut self, key: Keycode) -> RebindState {
+        match key {
+            Keycode::Escape => return RebindState::Canceled,
+            Keycode::Backspace => (),
+            key => self.bindings.bind(self.current(), key),
+        }
+        self.idx += 1;
+        if self.idx < self.actions.len() {
+            RebindState::Next(self.current())
+        } else {
+            RebindState::Done(self.bindings.clone())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arch::pad::Button;
+    use crate::ui::binding::{family_basic_key, Action, Bindings, Hotkey, RebindState, Rebinding};
+    use sdl2::keyboard::{Keycode, Mod};
+
+    #[test]
+    fn is_parse_bindings() {
+        let bindings =
+            Bindings::parse("# コメント\nX = pad1.a\n\nZ=pad2.turbo_b\nEscape = hotkey.quit\n")
+                .unwrap();
+        assert_eq!(bindings.action(Keycode::X), Some(Action::Pad(0, Button::A)));
+        assert_eq!(
+            bindings.action(Keycode::Z),
+            Some(Action::Turbo(1, Button::B))
+        );
+        assert_eq!(
+            bindings.action(Keycode::Escape),
+            Some(Action::Hotkey(Hotkey::Quit))
+        );
+        assert_eq!(bindings.action(Keycode::Return), None);
+
+        assert!(Bindings::parse("X pad1.a").is_err());
+        assert!(Bindings::parse("X = pad5.a").is_err());
+
+        let mut bindings = Bindings::parse("F5 = hotkey.save_state").unwrap();
+        let save_state = Action::Hotkey(Hotkey::SaveState);
+        assert_eq!(bindings.action(Keycode::F5), Some(save_state));
+        bindings.bind(save_state, Keycode::F6);
+        assert_eq!(bindings.action(Keycode::F5), None);
+        assert_eq!(bindings.keys(save_state), vec![Keycode::F6]);
+    }
+
+    #[test]
+    fn is_serialize_round_trip() {
+        let bindings = Bindings::default();
+        let text = bindings.serialize();
+        let parsed = Bindings::parse(&text).unwrap();
+        assert_eq!(parsed.serialize(), text);
+        for action in Action::all() {
+            let mut expected = bindings.keys(action);
+            let mut keys = parsed.keys(action);
+            expected.sort_by_key(|key| key.name());
+            keys.sort_by_key(|key| key.name());
+            assert_eq!(keys, expected);
+        }
+    }
+
+    #[test]
+    fn is_rebinding_input() {
+        let bindings = Bindings::parse("X = pad1.a\nZ = pad1.b").unwrap();
+        let mut rebind = Rebinding::new(&bindings);
+        assert_eq!(rebind.current(), Action::Pad(0, Button::A));
+        match rebind.input(Keycode::Q) {
+            RebindState::Next(action) => assert_eq!(action, Action::Pad(0, Button::B)),
+            _ => panic!("rebinding finished early"),
+        }
+        // Backspaceは現在の割り当てを維持
+        let done = loop {
+            match rebind.input(Keycode::Backspace) {
+                RebindState::Next(_) => (),
+                RebindState::Done(bindings) => break bindings,
+                RebindState::Canceled => panic!("rebinding canceled"),
+            }
+        };
+        assert_eq!(done.keys(Action::Pad(0, Button::A)), vec![Keycode::Q]);
+        assert_eq!(done.action(Keycode::X), None);
+        assert_eq!(done.keys(Action::Pad(0, Button::B)), vec![Keycode::Z]);
+
+        let mut rebind = Rebinding::new(&bindings);
+        assert!(matches!(
+            rebind.input(Keycode::Escape),
+            RebindState::Canceled
+        ));
+    }
+
+    #[test]
+    fn is_hotkey_with_gui_modifier() {
+        let bindings = Bindings::default();
+        assert_eq!(family_basic_key(Keycode::Q, Mod::NOMOD), Some((7, 1)));
+        assert_eq!(family_basic_key(Keycode::Q, Mod::LGUIMOD), None);
+        assert_eq!(
+            bindings.action(Keycode::Q),
+            Some(Action::Hotkey(Hotkey::Quit))
+        );
+        assert_eq!(family_basic_key(Keycode::Tab, Mod::NOMOD), None);
+    }
+}
@@ -0,0 +1,139 @@
+use std::fs;
+use std::path::Path;
+
+use crate::arch::input::{Expansion, MultiTap, Port2};
+use crate::arch::pad::Button;
+use crate::arch::power_pad::Side;
+use crate::ui::binding::Action;
+
+/// ゲームごとの設定
+/// ROMと同じ場所の<ROM名>.cfg
+#[derive(Debug, Default)]
+pub struct GameConfig {
+    pub multitap: MultiTap,
+    pub port2: Port2,
+    pub power_pad_side: Side,
+    pub expansion: Expansion,
+    /// データレコーダのWAV
+    pub tape: Option<String>,
+    /// 連射周期 (ポート, ボタン, フレーム)
+    pub turbo: Vec<(usize, Button, u32)>,
+}
+
+impl GameConfig {
+    pub fn path(rom: &str) -> String {
+        Path::new(rom)
+            .with_extension("cfg")
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    /// 1行1設定
+    /// <設定名> = <値>
+    pub fn parse(text: &str) -> Result<GameConfig, String> {
+        let mut config = GameConfig::default();
+        for (line_no, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut kv = line.splitn(2, '=');
+            let key = kv.next().map(str::trim).unwrap_or("");
+            let value = kv
+                .next()
+                .map(str::trim)
+                .ok_or_else(|| format!("line {}: missing '='", line_no + 1))?;
+            match key {
+                "multitap" => {
+                    config.multitap = match value {
+                        "none" => MultiTap::None,
+                        "four_score" => MultiTap::FourScore,
+                        "famicom" => MultiTap::Famicom,
+                        _ => {
+                            return Err(format!("line {}: unknown multitap {}", line_no + 1, value))
+                        }
+                    }
+                }
+                "port2" => {
+                    config.port2 = match value {
+                        "pad" => Port2::Pad,
+                        "zapper" => Port2::Zapper,
+                        "arkanoid" => Port2::Arkanoid,
+                        "power_pad" => Port2::PowerPad,
+                        _ => return Err(format!("line {}: unknown device {}", line_no + 1, value)),
+                    }
+                }
+                "power_pad_side" => {
+                    config.power_pad_side = match value {
+                        "a" => Side::A,
+                        "b" => Side::B,
+                        _ => return Err(format!("line {}: unknown side {}", line_no + 1, value)),
+                    }
+                }
+                "expansion" => {
+                    config.expansion = match value {
+                        "none" => Expansion::None,
+                        "arkanoid" => Expansion::Arkanoid,
+                        "keyboard" => Expansion::Keyboard,
+                        _ => return Err(format!("line {}: unknown device {}", line_no + 1, value)),
+                    }
+                }
+                "tape" => config.tape = Some(value.to_string()),
+                // turbo.pad1.a = 4
+                key if key.starts_with("turbo.") => {
+                    let (port, button) = match Action::from_name(&key["turbo.".len()..]) {
+                        Some(Action::Pad(port, button))
+                            if button == Button::A || button == Button::B =>
+                        {
+                            (port, button)
+                        }
+                        Some(Action::Pad(..)) => {
+                            return Err(format!("line {}: turbo is only for a and b", line_no + 1))
+                        }
+                        _ => return Err(format!("line {}: unknown button {}", line_no + 1, key)),
+                    };
+                    let frames = value
+                        .parse::<u32>()
+                        .map_err(|err| format!("line {}: {}", line_no + 1, err))?;
+                    config.turbo.push((port, button, frames));
+                }
+                _ => return Err(format!("line {}: unknown setting {}", line_no + 1, key)),
+            }
+        }
+        Ok(config)
+    }
+
+    /// 未指定なら<ROM名>.wav
+    pub fn tape_path(&self, rom: &str) -> String {
+        self.tape.clone().unwrap_or_else(|| {
+            Path::new(rom)
+                .with_extension("wav")
+                .to_string_lossy()
+                .into_owned()
+        })
+    }
+
+    pub fn load(rom: &str) -> Result<GameConfig, String> {
+        let path = GameConfig::path(rom);
+        if !Path::new(&path).exists() {
+            return Ok(GameConfig::default());
+        }
+        let text = fs::read_to_string(path).map_err(|err| err.to_string())?;
+        GameConfig::parse(&text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arch::pad::Button;
+    use crate::ui::config::GameConfig;
+
+    #[test]
+    fn is_turbo_only_a_b() {
+        let config = GameConfig::parse("turbo.pad1.a = 4\nturbo.pad2.b = 6").unwrap();
+        assert_eq!(config.turbo, vec![(0, Button::A, 4), (1, Button::B, 6)]);
+        assert!(GameConfig::parse("turbo.pad1.start = 4").is_err());
+        assert!(GameConfig::parse("turbo.pad1.up = 4").is_err());
+        assert!(GameConfig::parse("turbo.hotkey.quit = 4").is_err());
+    }
+}
@@ -39,3 +39,39 @@ pub(crate) fn generate_menu(canvas: &mut Canvas<Window>) {
             .unwrap();
     }
 }
+
+/// 設定中のメッセージ表示
+/// 空文字で消去
+pub(crate) fn generate_prompt(canvas: &mut Canvas<Window>, text: &str) {
+    canvas.set_draw_color(Color::RGB(0x45, 0x5A, 0x64));
+    canvas.fill_rect(Rect::new(512, 300, 200, 52)).unwrap();
+    if text.is_empty() {
+        return;
+    }
+
+    let ttf_context = sdl2::ttf::init().unwrap();
+    let font = ttf_context
+        .load_font("./resources/Roboto-Regular.ttf", 16)
+        .unwrap();
+
+    let texture_creator = canvas.texture_creator();
+    const PITCH: i32 = 20;
+    for (idx, line) in text.lines().enumerate() {
+        let surface = font
+            .render(line)
+            .blended(Color::RGB(0xFF, 0xFF, 0xFF))
+            .unwrap();
+        let texture = texture_creator
+            .create_texture_from_surface(&surface)
+            .unwrap();
+
+        let TextureQuery { width, height, .. } = texture.query();
+        canvas
+            .copy(
+                &texture,
+                None,
+                Rect::new(512 + 26, 304 + (idx as i32 * PITCH), width, height),
+            )
+            .unwrap();
+    }
+}
@@ -1,21 +1,56 @@
+pub mod binding;
+pub mod config;
 pub mod menu;
+pub mod overlay;
 pub mod sprite_map;
 
+use log::{info, warn};
+use sdl2::audio::AudioSpecDesired;
 use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
-use sdl2::pixels::Color;
+use sdl2::mouse::MouseButton;
+use sdl2::pixels::{Color, PixelFormatEnum};
 use sdl2::rect::Rect;
-use sdl2::render::TextureQuery;
+use sdl2::render::{Canvas, TextureQuery};
+use sdl2::video::Window;
 use std::cell::RefCell;
+use std::fs;
+use std::path::Path;
 use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use crate::arch::ppu::Mirroring;
+use crate::arch::apu::SAMPLE_RATE;
+use crate::arch::input::{Expansion, Port2};
+use crate::arch::snapshot::Snapshot;
 use crate::arch::Arch;
-use crate::parser;
+use crate::{parser, wav};
+use binding::{family_basic_key, Action, Bindings, Hotkey, RebindState, Rebinding, BINDINGS_PATH};
+use config::GameConfig;
+
+/// 早送り中に1ループで呼ぶArch::frameの回数 (通常は1回)
+const FAST_FORWARD_RATE: usize = 4;
+/// 音声キューの上限(byte) 約100ms
+const AUDIO_LATENCY: u32 = SAMPLE_RATE / 10 * 2;
+/// .savを書き出す間隔
+const SAVE_INTERVAL: Duration = Duration::from_secs(30);
 
 pub fn run() {
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
+    let audio = sdl_context
+        .audio()
+        .and_then(|audio| {
+            let spec = AudioSpecDesired {
+                freq: Some(SAMPLE_RATE as i32),
+                channels: Some(1),
+                samples: None,
+            };
+            audio.open_queue::<i16, _>(None, &spec)
+        })
+        .map_err(|err| warn!("audio: {}", err))
+        .ok();
+    if let Some(queue) = &audio {
+        queue.resume();
+    }
 
     let window = video_subsystem
         .window("", 712, 480)
@@ -26,13 +61,32 @@ pub fn run() {
     let canvas = Rc::new(RefCell::new(window.into_canvas().build().unwrap()));
 
     // nes側
-    let (prg, chr, flag) = parser::parser("./roms/test2.nes").unwrap();
-    let mirroring = if flag >> 0x01 == 1 {
-        Mirroring::Vertial
-    } else {
-        Mirroring::Horizontal
-    };
-    let arch = Arch::new(prg, chr, canvas.clone(), mirroring);
+    const ROM_PATH: &str = "./roms/test2.nes";
+    let rom = parser::parser(ROM_PATH).unwrap();
+    let arch = Arch::new(rom, canvas.clone()).unwrap();
+    let config = GameConfig::load(ROM_PATH).unwrap_or_else(|err| {
+        warn!("game config: {}, use default", err);
+        GameConfig::default()
+    });
+    arch.set_multitap(config.multitap);
+    arch.set_port2(config.port2);
+    arch.set_power_pad_side(config.power_pad_side);
+    let power_pad = config.port2 == Port2::PowerPad;
+    arch.set_expansion(config.expansion);
+    for (port, button, frames) in config.turbo.iter() {
+        arch.pad(*port).set_turbo_period(*button, *frames);
+    }
+    let keyboard = config.expansion == Expansion::Keyboard;
+    let tape_path = config.tape_path(ROM_PATH);
+    let save_path = Path::new(ROM_PATH)
+        .with_extension("sav")
+        .to_string_lossy()
+        .into_owned();
+    load_battery(&arch, &save_path);
+    arch.load_trainer();
+    // 変化がなければ書き出さない
+    let mut saved = arch.battery();
+    let mut save_timer = Instant::now();
     let character = arch.ppu.sprite_flush();
 
     let texture_creator = canvas.borrow().texture_creator();
@@ -51,29 +105,238 @@ pub fn run() {
         .unwrap();
     canvas.borrow_mut().present();
 
+    let mut bindings = Bindings::load(BINDINGS_PATH).unwrap_or_else(|err| {
+        warn!("bindings: {}, use default", err);
+        Bindings::default()
+    });
+    let mut rebinding: Option<Rebinding> = None;
+    let mut paused = false;
+    let mut fast_forward = false;
+    let mut snapshot: Option<Snapshot> = None;
+
     let mut event_pump = sdl_context.event_pump().unwrap();
     'running: loop {
         for event in event_pump.poll_event() {
             match event {
-                Event::Quit { .. }
-                | Event::KeyDown {
-                    keycode: Some(Keycode::Escape),
+                Event::Quit { .. } => break 'running,
+                Event::KeyDown {
+                    keycode: Some(key), ..
+                } if rebinding.is_some() => {
+                    let state = rebinding.as_mut().unwrap().input(key);
+                    let prompt = match state {
+                        RebindState::Next(action) => prompt(action),
+                        RebindState::Done(new_bindings) => {
+                            if let Err(err) = new_bindings.save(BINDINGS_PATH) {
+                                warn!("bindings: {}", err);
+                            }
+                            bindings = new_bindings;
+                            rebinding = None;
+                            String::new()
+                        }
+                        RebindState::Canceled => {
+                            rebinding = None;
+                            String::new()
+                        }
+                    };
+                    menu::generate_prompt(&mut canvas.borrow_mut(), &prompt);
+                }
+                // ファミリーベーシック キーボード
+                Event::KeyDown {
+                    keycode: Some(key),
+                    keymod,
                     ..
+                } if keyboard && family_basic_key(key, keymod).is_some() => {
+                    let (row, idx) = family_basic_key(key, keymod).unwrap();
+                    arch.keyboard().set_key(row, idx, true);
                 }
-                | Event::KeyDown {
-                    keycode: Some(Keycode::Q),
+                Event::KeyUp {
+                    keycode: Some(key),
+                    keymod,
                     ..
-                } => break 'running,
+                } if keyboard && family_basic_key(key, keymod).is_some() => {
+                    let (row, idx) = family_basic_key(key, keymod).unwrap();
+                    arch.keyboard().set_key(row, idx, false);
+                }
                 Event::KeyDown {
-                    keycode: Some(Keycode::R),
+                    keycode: Some(key),
+                    repeat,
                     ..
-                } => arch.reset(),
+                } => match bindings.action(key) {
+                    Some(Action::Pad(port, button)) => arch.pad(port).set_button(button, true),
+                    Some(Action::Turbo(port, button)) => arch.pad(port).set_turbo(button, true),
+                    Some(Action::PowerPad(position)) => {
+                        arch.power_pad().set_position(position, true)
+                    }
+                    Some(Action::Hotkey(_)) if repeat => (),
+                    Some(Action::Hotkey(hotkey)) => match hotkey {
+                        Hotkey::Quit => break 'running,
+                        Hotkey::Reset => arch.reset(),
+                        Hotkey::Pause => paused = !paused,
+                        Hotkey::SaveState => {
+                            snapshot = Some(arch.save_state());
+                            info!("state saved");
+                        }
+                        Hotkey::LoadState => match &snapshot {
+                            Some(snapshot) => arch.load_state(snapshot),
+                            None => warn!("state: not saved"),
+                        },
+                        Hotkey::FastForward => fast_forward = true,
+                        Hotkey::Screenshot => screenshot(&canvas.borrow()),
+                        Hotkey::Rebind => {
+                            let rebind = Rebinding::new(&bindings);
+                            menu::generate_prompt(
+                                &mut canvas.borrow_mut(),
+                                &prompt(rebind.current()),
+                            );
+                            for port in 0..4 {
+                                arch.pad(port).release_all();
+                            }
+                            arch.power_pad().release_all();
+                            arch.keyboard().release_all();
+                            rebinding = Some(rebind);
+                        }
+                        Hotkey::TapePlay => match wav::read(&tape_path) {
+                            Ok(tape) => arch.recorder().play(tape),
+                            Err(err) => warn!("tape: {}", err),
+                        },
+                        Hotkey::TapeRecord => arch.recorder().record(),
+                        Hotkey::TapeStop => stop_tape(&arch, &tape_path),
+                    },
+                    None => (),
+                },
+                Event::KeyUp {
+                    keycode: Some(key), ..
+                } => match bindings.action(key) {
+                    Some(Action::Pad(port, button)) => arch.pad(port).set_button(button, false),
+                    Some(Action::Turbo(port, button)) => arch.pad(port).set_turbo(button, false),
+                    Some(Action::PowerPad(position)) => {
+                        arch.power_pad().set_position(position, false)
+                    }
+                    Some(Action::Hotkey(Hotkey::FastForward)) => fast_forward = false,
+                    _ => (),
+                },
+                // 光線銃, バウス
+                // 画面は2倍で表示している
+                Event::MouseMotion { x, y, xrel, .. } => {
+                    arch.zapper()
+                        .set_cursor(Some((x as usize / 2, y as usize / 2)));
+                    arch.arkanoid().move_by(xrel);
+                }
+                Event::MouseButtonDown {
+                    mouse_btn: MouseButton::Left,
+                    ..
+                } => {
+                    arch.zapper().set_trigger(true);
+                    arch.arkanoid().set_button(true);
+                }
+                Event::MouseButtonUp {
+                    mouse_btn: MouseButton::Left,
+                    ..
+                } => {
+                    arch.zapper().set_trigger(false);
+                    arch.arkanoid().set_button(false);
+                }
                 _ => {}
             }
         }
 
-        arch.frame();
+        if !paused && rebinding.is_none() {
+            let steps = if fast_forward { FAST_FORWARD_RATE } else { 1 };
+            for _ in 0..steps {
+                arch.frame();
+            }
+        }
 
+        if let (Some(queue), Some(samples)) = (&audio, arch.audio_samples()) {
+            // 早送り中, 遅延が溜まった場合は捨てる
+            if !fast_forward && queue.size() < AUDIO_LATENCY {
+                queue.queue(&samples);
+            }
+        }
+
+        // CHR-RAMの書き換え, CHRバンクの切り替えをスプライト表示に反映
+        if arch.ppu.take_pattern_changed() {
+            let texture_creator = canvas.borrow().texture_creator();
+            let texture = sprite_map::generate_sprites(texture_creator, arch.ppu.sprite_flush());
+            canvas
+                .borrow_mut()
+                .copy(&texture, None, Rect::new(550, 10, width, height))
+                .unwrap();
+        }
+
+        if save_timer.elapsed() >= SAVE_INTERVAL {
+            save_timer = Instant::now();
+            let battery = arch.battery();
+            if battery != saved {
+                save_battery(&save_path, battery.as_deref());
+                saved = battery;
+            }
+        }
+
+        if power_pad {
+            overlay::generate_power_pad(&mut canvas.borrow_mut(), arch.power_pad());
+        }
         canvas.borrow_mut().present();
     }
+
+    stop_tape(&arch, &tape_path);
+    save_battery(&save_path, arch.battery().as_deref());
+}
+
+/// .savがあれば復元
+fn load_battery(arch: &Arch, path: &str) {
+    if arch.battery().is_none() || !Path::new(path).exists() {
+        return;
+    }
+    match fs::read(path) {
+        Ok(data) => arch.load_battery(&data),
+        Err(err) => warn!("save: {}", err),
+    }
+}
+
+/// バッテリーバックアップがあれば保存
+fn save_battery(path: &str, battery: Option<&[u8]>) {
+    if let Some(data) = battery {
+        match fs::write(path, data) {
+            Ok(_) => info!("save: {}", path),
+            Err(err) => warn!("save: {}", err),
+        }
+    }
+}
+
+/// 録音中ならWAVに保存
+fn stop_tape(arch: &Arch, path: &str) {
+    if let Some(tape) = arch.recorder().stop() {
+        match wav::write(path, &tape) {
+            Ok(_) => info!("tape: {}", path),
+            Err(err) => warn!("tape: {}", err),
+        }
+    }
+}
+
+fn prompt(action: Action) -> String {
+    format!("Press key for\n{}", action.name())
+}
+
+/// エミュレータ画面をPPMで保存
+fn screenshot(canvas: &Canvas<Window>) {
+    let (width, height) = (512, 480);
+    let pixels = match canvas.read_pixels(Rect::new(0, 0, width, height), PixelFormatEnum::RGB24) {
+        Ok(pixels) => pixels,
+        Err(err) => {
+            warn!("screenshot: {}", err);
+            return;
+        }
+    };
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let path = format!("./screenshot-{}.ppm", time);
+    let mut data = format!("P6\n{} {}\n255\n", width, height).into_bytes();
+    data.extend(pixels);
+    match fs::write(&path, data) {
+        Ok(_) => info!("screenshot: {}", path),
+        Err(err) => warn!("screenshot: {}", err),
+    }
 }
@@ -0,0 +1,50 @@
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+
+use crate::arch::power_pad::{PowerPad, COLUMNS, ROWS};
+
+/// パワーパッドのマットを画面左下に表示
+/// 押されているボタンは塗りつぶし
+pub(crate) fn generate_power_pad(canvas: &mut Canvas<Window>, pad: &PowerPad) {
+    const CELL: u32 = 16;
+    const PITCH: i32 = 20;
+    const LEFT: i32 = 8;
+    const TOP: i32 = 480 - 8 - PITCH * ROWS as i32;
+
+    canvas.set_draw_color(Color::RGB(0xEC, 0xEE, 0xEC));
+    canvas
+        .fill_rect(Rect::new(
+            LEFT - 4,
+            TOP - 4,
+            (PITCH * COLUMNS as i32 + 4) as u32,
+            (PITCH * ROWS as i32 + 4) as u32,
+        ))
+        .unwrap();
+
+    for position in 0..COLUMNS * ROWS {
+        let button = match pad.button(position) {
+            Some(button) => button,
+            None => continue,
+        };
+        let rect = Rect::new(
+            LEFT + PITCH * (position % COLUMNS) as i32,
+            TOP + PITCH * (position / COLUMNS) as i32,
+            CELL,
+            CELL,
+        );
+        // 中段は赤, 上下段は青
+        let color = if position / COLUMNS == 1 {
+            Color::RGB(0xD8, 0x28, 0x00)
+        } else {
+            Color::RGB(0x00, 0x58, 0xF8)
+        };
+        canvas.set_draw_color(color);
+        if pad.is_pressed(button) {
+            canvas.fill_rect(rect).unwrap();
+        } else {
+            canvas.draw_rect(rect).unwrap();
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use std::fs;
+
+/// テープの再生速度計算が桁あふれしない上限
+const MAX_RATE: u32 = 192_000;
+
+/// モノラル8bit PCM
+pub struct Wave {
+    pub rate: u32,
+    /// 0x80が無音
+    pub samples: Vec<u8>,
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Result<u16, String> {
+    data.get(offset..offset + 2)
+        .map(|b| u16::from(b[0]) | u16::from(b[1]) << 8)
+        .ok_or_else(|| "wav: unexpected end".to_string())
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Result<u32, String> {
+    Ok(u32::from(u16_at(data, offset)?) | u32::from(u16_at(data, offset + 2)?) << 16)
+}
+
+pub fn read(path: &str) -> Result<Wave, String> {
+    let data = fs::read(path).map_err(|err| err.to_string())?;
+    decode(&data)
+}
+
+pub fn write(path: &str, wave: &Wave) -> Result<(), String> {
+    fs::write(path, encode(wave)).map_err(|err| err.to_string())
+}
+
+/// PCMのみ対応
+/// 16bit, ステレオは1ch目を8bitに変換する
+fn decode(data: &[u8]) -> Result<Wave, String> {
+    if data.len() < 12 || &data[0..4] != b"RIFF" || &data[8..12] != b"WAVE" {
+        return Err("wav: not RIFF WAVE".to_string());
+    }
+
+    let mut format = None;
+    let mut offset = 12;
+    while offset + 8 <= data.len() {
+        let id = &data[offset..offset + 4];
+        let size = u32_at(data, offset + 4)? as usize;
+        let body = offset + 8;
+        match id {
+            b"fmt " => {
+                let tag = u16_at(data, body)?;
+                if tag != 1 {
+                    return Err(format!("wav: unsupported format {}", tag));
+                }
+                let channels = usize::from(u16_at(data, body + 2)?);
+                let rate = u32_at(data, body + 4)?;
+                if rate == 0 || rate > MAX_RATE {
+                    return Err(format!("wav: unsupported rate {}", rate));
+                }
+                let bits = u16_at(data, body + 14)?;
+                format = Some((channels, rate, bits));
+            }
+            b"data" => {
+                let (channels, rate, bits) = format.ok_or("wav: data before fmt")?;
+                let end = (body + size).min(data.len());
+                let frame = channels * usize::from(bits / 8);
+                if frame == 0 {
+                    return Err("wav: invalid fmt".to_string());
+                }
+                let samples = data[body..end]
+                    .chunks(frame)
+                    .filter(|frame| frame.len() == channels * usize::from(bits / 8))
+                    .map(|frame| match bits {
+                        8 => frame[0],
+                        // signed 16bit -> unsigned 8bit
+                        _ => frame[1].wrapping_add(0x80),
+                    })
+                    .collect();
+                return Ok(Wave { rate, samples });
+            }
+            _ => (),
+        }
+        // 奇数サイズはパディング
+        offset = body + size + (size & 1);
+    }
+    Err("wav: no data".to_string())
+}
+
+fn encode(wave: &Wave) -> Vec<u8> {
+    let size = wave.samples.len() as u32;
+    let mut data = Vec::with_capacity(44 + wave.samples.len());
+    data.extend_from_slice(b"RIFF");
+    data.extend_from_slice(&(36 + size).to_le_bytes());
+    data.extend_from_slice(b"WAVE");
+    data.extend_from_slice(b"fmt ");
+    data.extend_from_slice(&16u32.to_le_bytes());
+    // PCM, 1ch
+    data.extend_from_slice(&1u16.to_le_bytes());
+    data.extend_from_slice(&1u16.to_le_bytes());
+    data.extend_from_slice(&wave.rate.to_le_bytes());
+    data.extend_from_slice(&wave.rate.to_le_bytes());
+    // block align, bits
+    data.extend_from_slice(&1u16.to_le_bytes());
+    data.extend_from_slice(&8u16.to_le_bytes());
+    data.extend_from_slice(b"data");
+    data.extend_from_slice(&size.to_le_bytes());
+    data.extend_from_slice(&wave.samples);
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::wav::{decode, encode, Wave};
+
+    #[test]
+    fn is_round_trip() {
+        let wave = Wave {
+            rate: 44_100,
+            samples: vec![0x40, 0xC0, 0x80],
+        };
+        let data = encode(&wave);
+        assert_eq!(data.len(), 44 + 3);
+        assert_eq!(&data[0..4], b"RIFF");
+        assert_eq!(&data[4..8], &(36u32 + 3).to_le_bytes());
+        let decoded = decode(&data).unwrap();
+        assert_eq!(decoded.rate, 44_100);
+        assert_eq!(decoded.samples, wave.samples);
+    }
+
+    #[test]
+    fn is_16bit_stereo_first_channel() {
+        let mut data = Vec::new();
+        data.extend_from_slice(b"RIFF\x00\x00\x00\x00WAVE");
+        // 奇数サイズのチャンクはパディングを読み飛ばす
+        data.extend_from_slice(b"LIST\x01\x00\x00\x00\x00\x00");
+        data.extend_from_slice(b"fmt \x10\x00\x00\x00");
+        data.extend_from_slice(&[0x01, 0x00, 0x02, 0x00]);
+        data.extend_from_slice(&48_000u32.to_le_bytes());
+        data.extend_from_slice(&(48_000u32 * 4).to_le_bytes());
+        data.extend_from_slice(&[0x04, 0x00, 0x10, 0x00]);
+        data.extend_from_slice(b"data\x08\x00\x00\x00");
+        // L: 0x7FFF, -0x8000 / R: 0
+        data.extend_from_slice(&[0xFF, 0x7F, 0x00, 0x00, 0x00, 0x80, 0x00, 0x00]);
+        let wave = decode(&data).unwrap();
+        assert_eq!(wave.rate, 48_000);
+        assert_eq!(wave.samples, vec![0xFF, 0x00]);
+
+        assert!(decode(b"RIFF\x00\x00\x00\x00AVI ").is_err());
+        data[34..38].copy_from_slice(&384_000u32.to_le_bytes());
+        assert!(decode(&data).is_err());
+    }
+}